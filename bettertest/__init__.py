@@ -1,4 +1,8 @@
+import base64
+import json
+import os
 import sys
+import time
 
 import requests
 
@@ -12,7 +16,36 @@ class _StageMeta(type):
 
 
 class Stage(metaclass=_StageMeta):
-    pass
+    # set to True/False to override the pipedef-wide FAIL_FAST for this stage
+    fail_fast: bool | None = None
+
+
+# @needs(OtherStage) / @needs(OtherStage.task_x) on a stage or task: start as
+# soon as those pass instead of waiting for the previous stage. the boss reads
+# these from the pipedef source, so this is just a marker at runtime
+def needs(*deps: object):
+    def mark(target):
+        target.__bettertest_needs__ = deps
+        return target
+
+    return mark
+
+
+# @requires(mem="high") on a stage or task: only send its run(ANY, ...) calls to
+# workers with these labels. like @needs, the boss reads it from the source and
+# hands it to the task as BETTERTEST_LABELS
+def requires(**labels: str | bool):
+    def mark(target):
+        target.__bettertest_requires__ = labels
+        return target
+
+    return mark
+
+
+# the worker token from the boss's --worker-token, for workers that require one
+def _auth() -> dict[str, str]:
+    token = os.environ.get("BETTERTEST_WORKER_TOKEN")
+    return {"Authorization": f"Bearer {token}"} if token else {}
 
 
 def _check_worker(worker: str):
@@ -26,15 +59,203 @@ def _check_worker(worker: str):
         sys.exit(1)
 
 
-def run(worker: str, image: str, command: str) -> int:
-    _check_worker(worker)
-    print(f"running: {command}")
-    resp = requests.post(
-        f"{worker}/run", json={"image": image, "command": command}, stream=True
-    )
+# tells the boss things for the task's result: which worker it ended up on,
+# how the last container exited or that it timed out, and the junit reports of every run() so far
+def _note(
+    worker: str | None = None,
+    junit: str | None = None,
+    exit_code: int | None = None,
+    oom_killed: bool | None = None,
+    timed_out: bool | None = None,
+):
+    path = os.environ.get("BETTERTEST_RESULT")
+    if not path:
+        return
+    try:
+        with open(path) as f:
+            note = json.load(f)
+    except (OSError, ValueError):
+        note = {}
+    if worker is not None:
+        note["worker"] = worker
+    if junit is not None:
+        note.setdefault("junit", []).append(junit)
+    if exit_code is not None:
+        note["exit_code"] = exit_code
+    if oom_killed is not None:
+        note["oom_killed"] = oom_killed
+    if timed_out is not None:
+        note["timed_out"] = timed_out
+    with open(path, "w") as f:
+        json.dump(note, f)
+
+
+# keeps a file the worker copied out of the container in the task's artifacts
+# dir, which the boss serves. returns False for paths that would escape it
+def _save_artifact(artifact: dict[str, str]) -> bool:
+    path = os.path.normpath(artifact["path"])
+    if os.path.isabs(path) or path.split(os.sep)[0] == "..":
+        return False
+    dest = os.path.join(os.environ["BETTERTEST_ARTIFACTS"], path)
+    os.makedirs(os.path.dirname(dest), exist_ok=True)
+    with open(dest, "wb") as f:
+        f.write(base64.b64decode(artifact["data"]))
+    return True
+
+
+# run(ANY, ...) goes to whichever of the boss's --workers is least busy
+ANY = "*"
+
+# how long run() waits for a matching worker to come up, in seconds
+_LEASE_WAIT = 30
+
+
+# a worker from the boss's pool for ANY or a "key=value,..." label selector, on
+# top of the task's @requires. returns its url and the lease to hand back once
+# the container is done
+def _lease(selector: str) -> tuple[str, int]:
+    pool = os.environ.get("BETTERTEST_POOL")
+    if not pool:
+        print(
+            f"error: no worker pool to pick {selector!r} from — start the boss with --workers",
+            file=sys.stderr,
+        )
+        sys.exit(1)
+    labels: dict[str, str] = json.loads(os.environ.get("BETTERTEST_LABELS", "{}"))
+    if selector != ANY:
+        for label in selector.split(","):
+            key, _, value = label.partition("=")
+            labels[key.strip()] = value.strip()
+    # 503 means a matching worker is down or hasn't checked in yet, which the
+    # boss's health checks may fix. 404 means none ever will match
+    deadline = time.monotonic() + _LEASE_WAIT
+    waiting = False
+    while True:
+        resp = requests.post(
+            f"{pool}/api/workers/lease",
+            json={"task": os.environ.get("BETTERTEST_TASK", ""), "labels": labels},
+            headers=_auth(),
+            timeout=10,
+        )
+        if resp.status_code != 503 or time.monotonic() >= deadline:
+            break
+        if not waiting:
+            print(f"waiting for a worker: {resp.text}")
+            waiting = True
+        time.sleep(5)
+    if resp.status_code in (404, 503):
+        print(f"error: {resp.text}", file=sys.stderr)
+        sys.exit(1)
+    if resp.status_code == 401:
+        print(
+            "error: the boss's pool wants the worker token — BETTERTEST_WORKER_TOKEN doesn't match its --worker-token",
+            file=sys.stderr,
+        )
+        sys.exit(1)
     resp.raise_for_status()
-    event = None
+    lease = resp.json()
+    return lease["url"], lease["id"]
+
+
+def _release(lease: int):
+    try:
+        requests.delete(
+            f"{os.environ['BETTERTEST_POOL']}/api/workers/lease/{lease}",
+            headers=_auth(),
+            timeout=5,
+        )
+    except requests.RequestException:
+        pass
+
+
+def _kill_container(worker: str, container_id: str):
+    try:
+        requests.delete(f"{worker}/run/{container_id}", headers=_auth(), timeout=5)
+    except requests.RequestException:
+        pass
+
+
+# a named secret from the boss's --secrets file. values are masked in task output
+def secret(name: str) -> str:
+    secrets = json.loads(os.environ.get("BETTERTEST_SECRETS", "{}"))
+    if name not in secrets:
+        print(
+            f"error: no secret named {name} — is it in the boss's --secrets file?",
+            file=sys.stderr,
+        )
+        sys.exit(1)
+    return secrets[name]
+
+
+# bytes, or a docker-style size string like "512m" or "4g"
+def _size(value: int | str) -> int:
+    if isinstance(value, int):
+        return value
+    units = {"k": 1024, "m": 1024**2, "g": 1024**3}
+    v = value.strip().lower().removesuffix("b")
+    if v and v[-1] in units:
+        return int(float(v[:-1]) * units[v[-1]])
+    return int(v)
+
+
+def run(
+    worker: str,
+    image: str,
+    command: str,
+    timeout: int | None = None,
+    cpus: float | None = None,
+    memory: int | str | None = None,
+    pids: int | None = None,
+    shm_size: int | str | None = None,
+    env: dict[str, str] | None = None,
+    junit: str | None = None,
+    artifacts: list[str] | None = None,
+) -> int:
+    # a url, or ANY / "key=value,..." to let the boss pick from its pool
+    lease = None
+    if "://" not in worker:
+        worker, lease = _lease(worker)
+        print(f"worker: {worker}")
+    container_id = None
+    done = False
+    # from here on the lease has to go back, however this ends
     try:
+        _check_worker(worker)
+        _note(worker=worker)
+        print(f"running: {command}")
+        body: dict[str, object] = {"image": image, "command": command}
+        if timeout is not None:
+            body["timeout_secs"] = timeout
+        if env is not None:
+            body["env"] = env
+        # path of a JUnit XML report in the container, e.g. from `pytest --junitxml`
+        if junit is not None:
+            body["junit"] = junit
+        # files or directories in the container to keep, e.g. coverage reports.
+        # only collected when the boss has somewhere to put them
+        if artifacts and "BETTERTEST_ARTIFACTS" in os.environ:
+            body["artifacts"] = artifacts
+        # set by the boss when the run targets a specific commit
+        if "BETTERTEST_SHA" in os.environ:
+            body["checkout"] = {
+                "repo": os.environ["BETTERTEST_REPO"],
+                "sha": os.environ["BETTERTEST_SHA"],
+            }
+        body["limits"] = {
+            "cpus": cpus,
+            "memory_bytes": None if memory is None else _size(memory),
+            "pids": pids,
+            "shm_size_bytes": None if shm_size is None else _size(shm_size),
+        }
+        resp = requests.post(f"{worker}/run", json=body, headers=_auth(), stream=True)
+        if resp.status_code == 401:
+            print(
+                f"error: worker at {worker} wants a token — is the boss's --worker-token the same as the worker's?",
+                file=sys.stderr,
+            )
+            sys.exit(1)
+        resp.raise_for_status()
+        event = None
         for line in resp.iter_lines(decode_unicode=True):
             if line.startswith("event: "):
                 event = line[7:]
@@ -42,13 +263,40 @@ def run(worker: str, image: str, command: str) -> int:
                 data = line[6:]
                 if event == "error":
                     raise Exception(f"worker error: {data}")
+                if event == "queued":
+                    print(f"waiting for a free slot on the worker ({data} in line)")
+                if event == "started":
+                    container_id = data
+                if event == "junit":
+                    _note(junit=json.loads(data))
+                if event == "artifact":
+                    artifact = json.loads(data)
+                    if not _save_artifact(artifact):
+                        print(f"skipped artifact {artifact['path']}: outside the artifacts dir")
                 if event == "log":
                     print(data)
+                if event == "timeout":
+                    done = True
+                    _note(timed_out=True)
+                    print(f"\ntimed out after {data}s")
+                    sys.exit(1)
                 if event == "done":
-                    exit_code = int(data)
-                    print(f"\nexit code: {exit_code}")
+                    done = True
+                    result = json.loads(data)
+                    exit_code = result["exit_code"]
+                    _note(exit_code=exit_code, oom_killed=result["oom_killed"])
+                    if result["oom_killed"]:
+                        print("\nkilled: out of memory")
+                    else:
+                        print(f"\nexit code: {exit_code}")
                     return exit_code
     except requests.exceptions.ChunkedEncodingError:
         print("error: lost connection to worker", file=sys.stderr)
         sys.exit(1)
+    finally:
+        # bailing out early (e.g. the boss cancelled the run) — don't leave the container running
+        if container_id is not None and not done:
+            _kill_container(worker, container_id)
+        if lease is not None:
+            _release(lease)
     raise Exception("stream ended without done event")
@@ -1,14 +1,34 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TaskId {
+    pub stage: String,
+    pub task: String,
+}
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct StageDto {
     pub name: String,
     pub tasks: Vec<String>,
+    /// overrides PipelineDto::fail_fast for this stage
+    #[serde(default)]
+    pub fail_fast: Option<bool>,
+    /// task name -> tasks it declared with `@needs`. tasks missing here just
+    /// wait for the previous stage
+    #[serde(default)]
+    pub needs: BTreeMap<String, Vec<TaskId>>,
+    /// task name -> worker labels it declared with `@requires`, its stage's included
+    #[serde(default)]
+    pub labels: BTreeMap<String, BTreeMap<String, String>>,
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct PipelineDto {
     pub stages: Vec<StageDto>,
+    /// skip the remaining stages once a stage has a failed task
+    #[serde(default)]
+    pub fail_fast: bool,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -17,6 +37,9 @@ pub enum TaskState {
     Running,
     Pass,
     Fail,
+    Cancelled,
+    TimedOut,
+    Skipped,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -25,6 +48,76 @@ pub struct TaskRunState {
     pub state: TaskState,
     #[serde(default)]
     pub output: String,
+    #[serde(flatten)]
+    pub result: TaskResult,
+}
+
+/// how a task went, beyond its state. all None for tasks that never ran
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+pub struct TaskResult {
+    /// unix seconds
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    /// unix seconds
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// of the container the task's last `bettertest.run()` started, or of the
+    /// task's python process if no container finished. None if it didn't
+    /// start or was killed
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// that container was killed for running out of memory
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oom_killed: bool,
+    /// the worker the task's last `bettertest.run()` went to
+    #[serde(default)]
+    pub worker: Option<String>,
+    /// from the JUnit XML reports the task's `run()`s collected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<TestCase>,
+    /// files collected from the task's containers, relative to
+    /// `/api/run/{id}/artifacts/{stage}.{task}/`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<String>,
+}
+
+/// one `<testcase>` of a JUnit XML report
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TestCase {
+    /// the `classname`, e.g. `test.test_models.TestUser`
+    #[serde(default)]
+    pub class: String,
+    pub name: String,
+    pub status: TestStatus,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    /// the failure, error or skip message, plus its body (usually a traceback)
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    /// the test itself blew up, as opposed to an assertion failing
+    Error,
+    Skipped,
+}
+
+/// data of the `task_result` run event
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TaskResultEvent {
+    pub stage: String,
+    pub task: String,
+    pub passed: bool,
+    pub state: TaskState,
+    pub output: String,
+    #[serde(flatten)]
+    pub result: TaskResult,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -38,10 +131,104 @@ pub struct PipelineRunState {
     pub run_id: u32,
     pub active: bool,
     pub stages: Vec<StageRunState>,
+    /// unix seconds
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    /// unix seconds, None while the run is still going
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    /// set when this run reruns the failed tasks of an earlier one
+    #[serde(default)]
+    pub parent_run_id: Option<u32>,
+    /// waiting for a free run slot. queued runs are still `active`
+    #[serde(default)]
+    pub queued: bool,
+    /// the commit under test. None means whatever the image was built from
+    #[serde(default)]
+    pub git: Option<GitCheckout>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct GitCheckout {
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub sha: String,
+}
+
+/// optional body of `POST /api/run`
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct CreateRunRequest {
+    pub repo: String,
+    /// branch, tag or sha. defaults to the remote's HEAD
+    #[serde(rename = "ref", default = "default_ref")]
+    pub git_ref: String,
+}
+
+fn default_ref() -> String {
+    "HEAD".into()
+}
+
+/// response of `POST /api/run` and `POST /api/run/{id}/rerun-failed`
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RunCreated {
+    pub run_id: u32,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub run_id: u32,
+    pub active: bool,
+    pub queued: bool,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub parent_run_id: Option<u32>,
+    pub passed: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+impl RunSummary {
+    pub fn of(run: &PipelineRunState) -> Self {
+        let tasks = || run.stages.iter().flat_map(|s| &s.tasks);
+        RunSummary {
+            run_id: run.run_id,
+            active: run.active,
+            queued: run.queued,
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            parent_run_id: run.parent_run_id,
+            passed: tasks()
+                .filter(|t| t.state == TaskState::Pass)
+                .count(),
+            failed: tasks()
+                .filter(|t| matches!(t.state, TaskState::Fail | TaskState::TimedOut))
+                .count(),
+            total: tasks().count(),
+        }
+    }
+}
+
+/// why the pipedef didn't parse
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PipedefErrorDto {
+    /// e.g. `SyntaxError: '(' was never closed`
+    pub summary: String,
+    pub line: Option<u32>,
+    /// the full python traceback
+    pub details: String,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct StateResponse {
-    pub pipeline: PipelineDto,
+    /// None when the pipedef has never parsed since the boss started
+    pub pipeline: Option<PipelineDto>,
+    /// set while the pipedef on disk is broken
+    #[serde(default)]
+    pub pipeline_error: Option<PipedefErrorDto>,
+    /// the most recently created run
     pub run: Option<PipelineRunState>,
+    /// every queued or running run
+    #[serde(default)]
+    pub runs: Vec<RunSummary>,
 }
@@ -38,19 +38,29 @@ impl Theme {
 pub enum Msg {
     ToggleTheme,
     StartRun,
+    CancelRun,
+    RerunFailed,
     StateLoaded(StateResponse),
+    RunsLoaded(Vec<RunSummary>),
+    PipelineChanged(PipelineDto),
+    PipelineError(PipedefErrorDto),
+    SelectRun(u32),
     RunCreated {
         run_id: u32,
     },
     SseState(PipelineRunState),
-    StageStarted {
+    RunStarted,
+    TaskStarted {
         stage_name: String,
+        task_name: String,
     },
     TaskResult {
         stage_name: String,
         task_name: String,
-        passed: bool,
+        state: TaskState,
+        result: TaskResult,
     },
+    RunCancelled,
     RunDone,
 }
 
@@ -59,8 +69,11 @@ pub enum Msg {
 pub struct UpdateResult {
     pub changed: bool,
     pub start_run: bool,
+    pub cancel_run: Option<u32>,
+    pub rerun_failed: Option<u32>,
     pub open_sse: Option<u32>,
     pub close_sse: bool,
+    pub refresh_runs: bool,
 }
 
 pub fn update_state(
@@ -72,8 +85,11 @@ pub fn update_state(
     let mut result = UpdateResult {
         changed: false,
         start_run: false,
+        cancel_run: None,
+        rerun_failed: None,
         open_sse: None,
         close_sse: false,
+        refresh_runs: false,
     };
 
     match msg {
@@ -91,39 +107,82 @@ pub fn update_state(
             }
             result.changed = true;
         }
+        Msg::RunsLoaded(_) | Msg::PipelineChanged(_) | Msg::PipelineError(_) => {
+            // set in Component::update
+            result.changed = true;
+        }
+        Msg::SelectRun(run_id) => {
+            if run.as_ref().map(|r| r.run_id) != Some(run_id) {
+                *run = None;
+                result.close_sse = true;
+                result.open_sse = Some(run_id);
+                result.changed = true;
+            }
+        }
         Msg::StartRun => {
             *run = None;
             result.start_run = true;
             result.close_sse = true;
             result.changed = true;
         }
+        Msg::CancelRun => {
+            result.cancel_run = run
+                .as_ref()
+                .filter(|r| r.active)
+                .map(|r| r.run_id);
+        }
+        Msg::RerunFailed => {
+            if let Some(r) = run
+                .as_ref()
+                .filter(|r| !r.active && has_failures(r))
+            {
+                result.rerun_failed = Some(r.run_id);
+                *run = None;
+                result.close_sse = true;
+                result.changed = true;
+            }
+        }
         Msg::RunCreated { run_id } => {
             if pipeline.is_some() {
                 result.open_sse = Some(run_id);
             }
+            result.refresh_runs = true;
             result.changed = true;
         }
         Msg::SseState(state) => {
             *run = Some(state);
             result.changed = true;
         }
-        Msg::StageStarted { stage_name } => {
+        Msg::RunStarted => {
+            if let Some(r) = run {
+                r.queued = false;
+            }
+            result.refresh_runs = true;
+            result.changed = true;
+        }
+        Msg::TaskStarted {
+            stage_name,
+            task_name,
+        } => {
             if let Some(r) = run
                 && let Some(stage) = r
                     .stages
                     .iter_mut()
                     .find(|s| s.name == stage_name)
+                && let Some(task) = stage
+                    .tasks
+                    .iter_mut()
+                    .find(|t| t.name == task_name)
             {
-                for task in &mut stage.tasks {
-                    task.state = TaskState::Running;
-                }
+                task.state = TaskState::Running;
             }
             result.changed = true;
         }
         Msg::TaskResult {
             stage_name,
             task_name,
-            passed,
+            state,
+            result: task_result,
         } => {
             if let Some(r) = run
                 && let Some(stage) = r
@@ -135,16 +194,30 @@ pub fn update_state(
                     .iter_mut()
                     .find(|t| t.name == task_name)
             {
-                task.state = if passed {
-                    TaskState::Pass
-                } else {
-                    TaskState::Fail
-                };
+                task.state = state;
+                task.result = task_result;
+            }
+            result.changed = true;
+        }
+        Msg::RunCancelled => {
+            if let Some(r) = run {
+                for task in r
+                    .stages
+                    .iter_mut()
+                    .flat_map(|s| &mut s.tasks)
+                    .filter(|t| matches!(t.state, TaskState::Pending | TaskState::Running))
+                {
+                    task.state = TaskState::Cancelled;
+                }
             }
             result.changed = true;
         }
         Msg::RunDone => {
+            if let Some(r) = run {
+                r.active = false;
+            }
             result.close_sse = true;
+            result.refresh_runs = true;
             result.changed = true;
         }
     }
@@ -152,37 +225,125 @@ pub fn update_state(
     result
 }
 
+pub fn has_failures(run: &PipelineRunState) -> bool {
+    run.stages
+        .iter()
+        .flat_map(|s| &s.tasks)
+        .any(|t| matches!(t.state, TaskState::Fail | TaskState::TimedOut))
+}
+
 // --- view (pure functions, separated for testing) ---
 
 pub fn view_app(
     pipeline: &Option<PipelineDto>,
+    pipeline_error: &Option<PipedefErrorDto>,
     run: &Option<PipelineRunState>,
+    runs: &[RunSummary],
     theme: &Theme,
     link: &yew::html::Scope<App>,
 ) -> Html {
     html! {
         <div class={theme.class()}>
             <header>
-                <h1>{ "bettertest" }</h1>
+                <h1>{ "bettertest" }{ view_commit(run) }</h1>
                 <div class="buttons">
                     <button onclick={link.callback(|_| Msg::StartRun)} disabled={pipeline.is_none()}>
                         { "new run" }
                     </button>
+                    <button onclick={link.callback(|_| Msg::CancelRun)} disabled={!run.as_ref().is_some_and(|r| r.active)}>
+                        { "cancel" }
+                    </button>
+                    <button onclick={link.callback(|_| Msg::RerunFailed)} disabled={!run.as_ref().is_some_and(|r| !r.active && has_failures(r))}>
+                        { "rerun failed" }
+                    </button>
                     <button onclick={link.callback(|_| Msg::ToggleTheme)}>
                         { theme.toggle_label() }
                     </button>
                 </div>
             </header>
+            { view_pipeline_error(pipeline_error, pipeline.is_some()) }
+            { view_runs(runs, run.as_ref().map(|r| r.run_id), link.callback(Msg::SelectRun)) }
             { view_run(run, theme) }
         </div>
     }
 }
 
+/// the pipedef doesn't parse. if it did earlier, new runs still use that version
+pub fn view_pipeline_error(error: &Option<PipedefErrorDto>, has_pipeline: bool) -> Html {
+    let Some(e) = error else {
+        return html! {};
+    };
+    let fallback = if has_pipeline {
+        "pipedef broken, still running the last version that parsed"
+    } else {
+        "pipedef broken, nothing can run until it's fixed"
+    };
+    let summary = match e.line {
+        Some(line) => format!("line {line}: {}", e.summary),
+        None => e.summary.clone(),
+    };
+    html! {
+        <div class="banner">
+            <p>{ fallback }</p>
+            <p class="summary">{ summary }</p>
+            <pre>{ &e.details }</pre>
+        </div>
+    }
+}
+
+/// `repo @ ref (sha)` of the commit the run is testing, if it targets one
+pub fn view_commit(run: &Option<PipelineRunState>) -> Html {
+    let Some(git) = run.as_ref().and_then(|r| r.git.as_ref()) else {
+        return html! {};
+    };
+    let short = git.sha.get(..7).unwrap_or(&git.sha);
+    html! {
+        <span class="commit" title={git.sha.clone()}>
+            { format!("{} @ {} ({short})", git.repo, git.git_ref) }
+        </span>
+    }
+}
+
+/// queued and running runs, shown once there's more than one to pick from
+pub fn view_runs(runs: &[RunSummary], selected: Option<u32>, on_select: Callback<u32>) -> Html {
+    if runs.len() < 2 {
+        return html! {};
+    }
+    html! {
+        <nav class="runs">
+            { for runs.iter().map(|r| {
+                let run_id = r.run_id;
+                let label = if r.queued {
+                    format!("#{run_id} queued")
+                } else {
+                    format!("#{run_id} running")
+                };
+                html! {
+                    <button
+                        class={(selected == Some(run_id)).then_some("selected")}
+                        onclick={on_select.reform(move |_| run_id)}
+                    >
+                        { label }
+                    </button>
+                }
+            }) }
+        </nav>
+    }
+}
+
 pub fn view_run(run: &Option<PipelineRunState>, theme: &Theme) -> Html {
     match run {
         None => html! {},
         Some(r) => html! {
-            { for r.stages.iter().map(|s| view_stage(s, r.run_id, theme)) }
+            <>
+                if let Some(parent) = r.parent_run_id {
+                    <p class="parent">{ format!("rerun of failed tasks from #{parent}") }</p>
+                }
+                if r.queued {
+                    <p class="queued">{ "queued, waiting for a free run slot" }</p>
+                }
+                { for r.stages.iter().map(|s| view_stage(s, r.run_id, theme)) }
+            </>
         },
     }
 }
@@ -204,6 +365,9 @@ pub fn view_task(task: &TaskRunState, stage_name: &str, run_id: u32, theme: &The
         TaskState::Running => "running",
         TaskState::Pass => "pass",
         TaskState::Fail => "fail",
+        TaskState::Cancelled => "cancelled",
+        TaskState::TimedOut => "timedout",
+        TaskState::Skipped => "skipped",
     };
     let href = format!(
         "/logs?run={run_id}&stage={stage_name}&task={}&theme={}",
@@ -211,7 +375,23 @@ pub fn view_task(task: &TaskRunState, stage_name: &str, run_id: u32, theme: &The
         theme.class()
     );
     html! {
-        <li class={class}><a href={href}>{ &task.name }</a></li>
+        <li class={class}>
+            <a href={href}>{ &task.name }</a>
+            if let Some(ms) = task.result.duration_ms {
+                <span class="duration">{ format_duration(ms) }</span>
+            }
+        </li>
+    }
+}
+
+/// `850ms`, `12.3s`, `4m 05s`, `1h 02m`
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match secs {
+        0 => format!("{ms}ms"),
+        1..60 => format!("{:.1}s", ms as f64 / 1000.0),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
     }
 }
 
@@ -219,13 +399,56 @@ pub fn view_task(task: &TaskRunState, stage_name: &str, run_id: u32, theme: &The
 
 pub struct App {
     pipeline: Option<PipelineDto>,
+    pipeline_error: Option<PipedefErrorDto>,
     run: Option<PipelineRunState>,
+    runs: Vec<RunSummary>,
     theme: Theme,
     event_source: Option<EventSource>,
     _closures: Vec<Closure<dyn FnMut(web_sys::MessageEvent)>>,
+    /// boss-wide events, open for as long as the page is
+    boss_events: Option<EventSource>,
+    _boss_closures: Vec<Closure<dyn FnMut(web_sys::MessageEvent)>>,
 }
 
 impl App {
+    fn open_boss_events(&mut self, link: &yew::html::Scope<Self>) {
+        let Ok(es) = EventSource::new("/api/events") else {
+            return;
+        };
+
+        // pipeline_changed (pipedef reloaded)
+        {
+            let link = link.clone();
+            let cb = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
+                if let Some(data) = e.data().as_string()
+                    && let Ok(pipeline) = serde_json::from_str::<PipelineDto>(&data)
+                {
+                    link.send_message(Msg::PipelineChanged(pipeline));
+                }
+            }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+            es.add_event_listener_with_callback("pipeline_changed", cb.as_ref().unchecked_ref())
+                .ok();
+            self._boss_closures.push(cb);
+        }
+
+        // pipeline_error (pipedef reload failed)
+        {
+            let link = link.clone();
+            let cb = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
+                if let Some(data) = e.data().as_string()
+                    && let Ok(error) = serde_json::from_str::<PipedefErrorDto>(&data)
+                {
+                    link.send_message(Msg::PipelineError(error));
+                }
+            }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+            es.add_event_listener_with_callback("pipeline_error", cb.as_ref().unchecked_ref())
+                .ok();
+            self._boss_closures.push(cb);
+        }
+
+        self.boss_events = Some(es);
+    }
+
     fn close_event_source(&mut self) {
         if let Some(es) = self.event_source.take() {
             es.close();
@@ -256,20 +479,33 @@ impl App {
             self._closures.push(cb);
         }
 
-        // stage_started
+        // run_started (left the queue)
+        {
+            let link = link.clone();
+            let cb = Closure::wrap(Box::new(move |_e: web_sys::MessageEvent| {
+                link.send_message(Msg::RunStarted);
+            }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+            es.add_event_listener_with_callback("run_started", cb.as_ref().unchecked_ref())
+                .ok();
+            self._closures.push(cb);
+        }
+
+        // task_started
         {
             let link = link.clone();
             let cb = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
                 if let Some(data) = e.data().as_string()
                     && let Ok(val) = serde_json::from_str::<serde_json::Value>(&data)
                     && let Some(stage) = val["stage"].as_str()
+                    && let Some(task) = val["task"].as_str()
                 {
-                    link.send_message(Msg::StageStarted {
+                    link.send_message(Msg::TaskStarted {
                         stage_name: stage.to_string(),
+                        task_name: task.to_string(),
                     });
                 }
             }) as Box<dyn FnMut(web_sys::MessageEvent)>);
-            es.add_event_listener_with_callback("stage_started", cb.as_ref().unchecked_ref())
+            es.add_event_listener_with_callback("task_started", cb.as_ref().unchecked_ref())
                 .ok();
             self._closures.push(cb);
         }
@@ -283,11 +519,18 @@ impl App {
                     && let Some(stage) = val["stage"].as_str()
                     && let Some(task) = val["task"].as_str()
                 {
-                    let passed = val["passed"].as_bool().unwrap_or(false);
+                    let state = serde_json::from_value(val["state"].clone()).unwrap_or(
+                        if val["passed"].as_bool().unwrap_or(false) {
+                            TaskState::Pass
+                        } else {
+                            TaskState::Fail
+                        },
+                    );
                     link.send_message(Msg::TaskResult {
                         stage_name: stage.to_string(),
                         task_name: task.to_string(),
-                        passed,
+                        state,
+                        result: serde_json::from_value(val.clone()).unwrap_or_default(),
                     });
                 }
             }) as Box<dyn FnMut(web_sys::MessageEvent)>);
@@ -296,6 +539,17 @@ impl App {
             self._closures.push(cb);
         }
 
+        // run_cancelled
+        {
+            let link = link.clone();
+            let cb = Closure::wrap(Box::new(move |_e: web_sys::MessageEvent| {
+                link.send_message(Msg::RunCancelled);
+            }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+            es.add_event_listener_with_callback("run_cancelled", cb.as_ref().unchecked_ref())
+                .ok();
+            self._closures.push(cb);
+        }
+
         // run_done
         {
             let link = link.clone();
@@ -311,32 +565,92 @@ impl App {
     }
 }
 
+fn post_new_run(url: String, link: yew::html::Scope<App>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let Ok(resp) = Request::post(&url).send().await else {
+            return;
+        };
+        let Ok(body) = resp.text().await else {
+            return;
+        };
+        let Ok(val) = serde_json::from_str::<serde_json::Value>(&body) else {
+            return;
+        };
+        let Some(run_id) = val["run_id"].as_u64() else {
+            return;
+        };
+        link.send_message(Msg::RunCreated {
+            run_id: run_id as u32,
+        });
+    });
+}
+
+fn fetch_runs(link: yew::html::Scope<App>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Ok(resp) = Request::get("/api/state").send().await
+            && let Ok(state) = resp.json::<StateResponse>().await
+        {
+            link.send_message(Msg::RunsLoaded(state.runs));
+        }
+    });
+}
+
 impl Component for App {
     type Message = Msg;
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
         let link = ctx.link().clone();
+        // `/?run=N` (e.g. linked from a commit status) shows that run instead of the latest
+        let pinned: Option<u32> = web_sys::window()
+            .and_then(|w| w.location().search().ok())
+            .and_then(|search| get_query_param(&search, "run"))
+            .and_then(|s| s.parse().ok());
         wasm_bindgen_futures::spawn_local(async move {
             if let Ok(resp) = Request::get("/api/state").send().await
-                && let Ok(state) = resp.json::<StateResponse>().await
+                && let Ok(mut state) = resp.json::<StateResponse>().await
             {
+                if let Some(run_id) = pinned
+                    && let Ok(resp) = Request::get(&format!("/api/run/{run_id}"))
+                        .send()
+                        .await
+                    && let Ok(run) = resp.json::<PipelineRunState>().await
+                {
+                    state.run = Some(run);
+                }
                 link.send_message(Msg::StateLoaded(state));
             }
         });
 
-        Self {
+        let mut app = Self {
             pipeline: None,
+            pipeline_error: None,
             run: None,
+            runs: vec![],
             theme: Theme::Dark,
             event_source: None,
             _closures: vec![],
-        }
+            boss_events: None,
+            _boss_closures: vec![],
+        };
+        app.open_boss_events(ctx.link());
+        app
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
-        if let Msg::StateLoaded(ref s) = msg {
-            self.pipeline = Some(s.pipeline.clone());
+        match msg {
+            Msg::StateLoaded(ref s) => {
+                self.pipeline = s.pipeline.clone();
+                self.pipeline_error = s.pipeline_error.clone();
+                self.runs = s.runs.clone();
+            }
+            Msg::RunsLoaded(ref runs) => self.runs = runs.clone(),
+            Msg::PipelineChanged(ref pipeline) => {
+                self.pipeline = Some(pipeline.clone());
+                self.pipeline_error = None;
+            }
+            Msg::PipelineError(ref e) => self.pipeline_error = Some(e.clone()),
+            _ => {}
         }
 
         let result = update_state(&self.pipeline, &mut self.run, &mut self.theme, msg);
@@ -347,24 +661,22 @@ impl Component for App {
         if let Some(run_id) = result.open_sse {
             self.open_event_source(run_id, ctx.link());
         }
+        if result.refresh_runs {
+            fetch_runs(ctx.link().clone());
+        }
         if result.start_run {
-            let link = ctx.link().clone();
+            post_new_run("/api/run".into(), ctx.link().clone());
+        }
+        if let Some(run_id) = result.rerun_failed {
+            post_new_run(
+                format!("/api/run/{run_id}/rerun-failed"),
+                ctx.link().clone(),
+            );
+        }
+        if let Some(run_id) = result.cancel_run {
             wasm_bindgen_futures::spawn_local(async move {
-                let Ok(resp) = Request::post("/api/run").send().await else {
-                    return;
-                };
-                let Ok(body) = resp.text().await else {
-                    return;
-                };
-                let Ok(val) = serde_json::from_str::<serde_json::Value>(&body) else {
-                    return;
-                };
-                let Some(run_id) = val["run_id"].as_u64() else {
-                    return;
-                };
-                link.send_message(Msg::RunCreated {
-                    run_id: run_id as u32,
-                });
+                let url = format!("/api/run/{run_id}/cancel");
+                let _ = Request::post(&url).send().await;
             });
         }
 
@@ -372,7 +684,14 @@ impl Component for App {
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        view_app(&self.pipeline, &self.run, &self.theme, ctx.link())
+        view_app(
+            &self.pipeline,
+            &self.pipeline_error,
+            &self.run,
+            &self.runs,
+            &self.theme,
+            ctx.link(),
+        )
     }
 }
 
@@ -381,19 +700,27 @@ impl Component for App {
 pub enum LogsMsg {
     SetOutput(String),
     AppendLine(String),
+    SetResult(TaskResult),
     ToggleTheme,
 }
 
 pub struct LogsPage {
+    pub run_id: u32,
     pub stage: String,
     pub task: String,
     pub theme: Theme,
     pub output: String,
+    pub result: TaskResult,
     event_source: Option<EventSource>,
     _closures: Vec<Closure<dyn FnMut(web_sys::MessageEvent)>>,
 }
 
-pub fn update_logs(theme: &mut Theme, output: &mut String, msg: LogsMsg) -> bool {
+pub fn update_logs(
+    theme: &mut Theme,
+    output: &mut String,
+    result: &mut TaskResult,
+    msg: LogsMsg,
+) -> bool {
     match msg {
         LogsMsg::SetOutput(text) => {
             *output = text;
@@ -406,6 +733,10 @@ pub fn update_logs(theme: &mut Theme, output: &mut String, msg: LogsMsg) -> bool
             output.push_str(&line);
             true
         }
+        LogsMsg::SetResult(r) => {
+            *result = r;
+            true
+        }
         LogsMsg::ToggleTheme => {
             *theme = theme.toggled();
             true
@@ -414,10 +745,12 @@ pub fn update_logs(theme: &mut Theme, output: &mut String, msg: LogsMsg) -> bool
 }
 
 pub fn view_logs_page(
+    run_id: u32,
     stage: &str,
     task: &str,
     theme: &Theme,
     output: &str,
+    result: &TaskResult,
     on_toggle: Callback<MouseEvent>,
 ) -> Html {
     html! {
@@ -430,11 +763,108 @@ pub fn view_logs_page(
                     <button onclick={on_toggle}>{ theme.toggle_label() }</button>
                 </div>
             </header>
+            { view_tests(&result.tests) }
+            { view_artifacts(run_id, stage, task, &result.artifacts) }
             <pre class="logs">{ output }</pre>
         </div>
     }
 }
 
+/// the task's JUnit test cases, failures first. nothing if it had no report
+pub fn view_tests(tests: &[TestCase]) -> Html {
+    if tests.is_empty() {
+        return html! {};
+    }
+    let count = |status| {
+        tests
+            .iter()
+            .filter(|t| t.status == status)
+            .count()
+    };
+    let failed = count(TestStatus::Failed) + count(TestStatus::Error);
+    let summary = format!(
+        "{} tests: {} passed, {failed} failed, {} skipped",
+        tests.len(),
+        count(TestStatus::Passed),
+        count(TestStatus::Skipped)
+    );
+    let mut sorted: Vec<_> = tests.iter().collect();
+    sorted.sort_by_key(|t| match t.status {
+        TestStatus::Failed | TestStatus::Error => 0,
+        TestStatus::Skipped => 1,
+        TestStatus::Passed => 2,
+    });
+    html! {
+        <section class="tests">
+            <h2>{ summary }</h2>
+            <ul>
+                { for sorted.into_iter().map(view_test) }
+            </ul>
+        </section>
+    }
+}
+
+/// links to the files the task collected with `run(artifacts=...)`
+pub fn view_artifacts(run_id: u32, stage: &str, task: &str, artifacts: &[String]) -> Html {
+    if artifacts.is_empty() {
+        return html! {};
+    }
+    html! {
+        <section class="artifacts">
+            <h2>{ format!("{} artifacts", artifacts.len()) }</h2>
+            <ul>
+                { for artifacts.iter().map(|path| {
+                    let href = format!(
+                        "/api/run/{run_id}/artifacts/{}.{}/{}",
+                        encode_path(stage),
+                        encode_path(task),
+                        encode_path(path)
+                    );
+                    html! { <li><a href={href} download="">{ path }</a></li> }
+                }) }
+            </ul>
+        </section>
+    }
+}
+
+/// percent-encode everything but unreserved characters and `/`
+fn encode_path(path: &str) -> String {
+    let mut out = String::new();
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn view_test(test: &TestCase) -> Html {
+    let class = match test.status {
+        TestStatus::Passed => "pass",
+        TestStatus::Failed => "fail",
+        TestStatus::Error => "error",
+        TestStatus::Skipped => "skipped",
+    };
+    let name = if test.class.is_empty() {
+        test.name.clone()
+    } else {
+        format!("{}.{}", test.class, test.name)
+    };
+    html! {
+        <li class={class}>
+            { name }
+            if let Some(ms) = test.duration_ms {
+                <span class="duration">{ format_duration(ms) }</span>
+            }
+            if let Some(message) = &test.message {
+                <pre>{ message }</pre>
+            }
+        </li>
+    }
+}
+
 fn get_query_param(search: &str, key: &str) -> Option<String> {
     let s = search.strip_prefix('?').unwrap_or(search);
     s.split('&')
@@ -443,14 +873,12 @@ fn get_query_param(search: &str, key: &str) -> Option<String> {
         .map(|(_, v)| v.to_string())
 }
 
-fn find_task_output(state: &PipelineRunState, stage: &str, task: &str) -> Option<String> {
+fn find_task<'a>(state: &'a PipelineRunState, stage: &str, task: &str) -> Option<&'a TaskRunState> {
     state
         .stages
         .iter()
         .find(|s| s.name == stage)
         .and_then(|s| s.tasks.iter().find(|t| t.name == task))
-        .filter(|t| !t.output.is_empty())
-        .map(|t| t.output.clone())
 }
 
 impl Component for LogsPage {
@@ -469,6 +897,7 @@ impl Component for LogsPage {
             .unwrap_or(0);
 
         let mut page = Self {
+            run_id,
             stage,
             task,
             theme: match get_query_param(&search, "theme").as_deref() {
@@ -476,6 +905,7 @@ impl Component for LogsPage {
                 _ => Theme::Dark,
             },
             output: String::new(),
+            result: TaskResult::default(),
             event_source: None,
             _closures: vec![],
         };
@@ -488,15 +918,17 @@ impl Component for LogsPage {
     }
 
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        update_logs(&mut self.theme, &mut self.output, msg)
+        update_logs(&mut self.theme, &mut self.output, &mut self.result, msg)
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         view_logs_page(
+            self.run_id,
             &self.stage,
             &self.task,
             &self.theme,
             &self.output,
+            &self.result,
             ctx.link().callback(|_| LogsMsg::ToggleTheme),
         )
     }
@@ -515,7 +947,7 @@ impl LogsPage {
             return;
         };
 
-        // state snapshot — extract output for our task
+        // state snapshot — extract output and result for our task
         {
             let link = link.clone();
             let stage = self.stage.clone();
@@ -523,9 +955,12 @@ impl LogsPage {
             let cb = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
                 if let Some(data) = e.data().as_string()
                     && let Ok(state) = serde_json::from_str::<PipelineRunState>(&data)
-                    && let Some(output) = find_task_output(&state, &stage, &task)
+                    && let Some(t) = find_task(&state, &stage, &task)
                 {
-                    link.send_message(LogsMsg::SetOutput(output));
+                    if !t.output.is_empty() {
+                        link.send_message(LogsMsg::SetOutput(t.output.clone()));
+                    }
+                    link.send_message(LogsMsg::SetResult(t.result.clone()));
                 }
             }) as Box<dyn FnMut(web_sys::MessageEvent)>);
             es.add_event_listener_with_callback("state", cb.as_ref().unchecked_ref())
@@ -553,19 +988,19 @@ impl LogsPage {
             self._closures.push(cb);
         }
 
-        // task_result — get output when our task finishes
+        // task_result — get output and result when our task finishes
         {
             let link = link.clone();
             let stage = self.stage.clone();
             let task = self.task.clone();
             let cb = Closure::wrap(Box::new(move |e: web_sys::MessageEvent| {
                 if let Some(data) = e.data().as_string()
-                    && let Ok(val) = serde_json::from_str::<serde_json::Value>(&data)
-                    && val["stage"].as_str() == Some(&stage)
-                    && val["task"].as_str() == Some(&task)
-                    && let Some(output) = val["output"].as_str()
+                    && let Ok(result) = serde_json::from_str::<TaskResultEvent>(&data)
+                    && result.stage == stage
+                    && result.task == task
                 {
-                    link.send_message(LogsMsg::SetOutput(output.to_string()));
+                    link.send_message(LogsMsg::SetOutput(result.output));
+                    link.send_message(LogsMsg::SetResult(result.result));
                 }
             }) as Box<dyn FnMut(web_sys::MessageEvent)>);
             es.add_event_listener_with_callback("task_result", cb.as_ref().unchecked_ref())
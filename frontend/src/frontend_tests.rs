@@ -29,12 +29,19 @@ fn test_pipeline() -> PipelineDto {
             StageDto {
                 name: "build".into(),
                 tasks: vec!["compile".into(), "lint".into()],
+                fail_fast: None,
+                needs: Default::default(),
+                labels: Default::default(),
             },
             StageDto {
                 name: "test".into(),
                 tasks: vec!["unit".into(), "integration".into()],
+                fail_fast: None,
+                needs: Default::default(),
+                labels: Default::default(),
             },
         ],
+        fail_fast: false,
     }
 }
 
@@ -42,6 +49,11 @@ fn fresh_run(pipeline: &PipelineDto) -> PipelineRunState {
     PipelineRunState {
         run_id: 0,
         active: false,
+        started_at: None,
+        finished_at: None,
+        parent_run_id: None,
+        queued: false,
+        git: None,
         stages: pipeline
             .stages
             .iter()
@@ -54,6 +66,7 @@ fn fresh_run(pipeline: &PipelineDto) -> PipelineRunState {
                         name: t.clone(),
                         state: TaskState::Pending,
                         output: String::new(),
+                        result: TaskResult::default(),
                     })
                     .collect(),
             })
@@ -66,6 +79,7 @@ fn task(name: &str, state: TaskState) -> TaskRunState {
         name: name.into(),
         state,
         output: String::new(),
+        result: TaskResult::default(),
     }
 }
 
@@ -125,7 +139,7 @@ fn run_created_without_pipeline_is_noop() {
 }
 
 #[test]
-fn stage_started_sets_tasks_running() {
+fn task_started_sets_task_running() {
     let pipeline = test_pipeline();
     let mut theme = Theme::Dark;
     let mut run = Some(fresh_run(&pipeline));
@@ -134,22 +148,22 @@ fn stage_started_sets_tasks_running() {
         &Some(pipeline),
         &mut run,
         &mut theme,
-        Msg::StageStarted {
+        Msg::TaskStarted {
             stage_name: "build".into(),
+            task_name: "lint".into(),
         },
     );
 
     let r = run.unwrap();
-    for task in &r.stages[0].tasks {
-        assert_eq!(task.state, TaskState::Running);
-    }
+    assert_eq!(r.stages[0].tasks[0].state, TaskState::Pending);
+    assert_eq!(r.stages[0].tasks[1].state, TaskState::Running);
     for task in &r.stages[1].tasks {
         assert_eq!(task.state, TaskState::Pending);
     }
 }
 
 #[test]
-fn stage_started_unknown_stage_is_noop() {
+fn task_started_unknown_task_is_noop() {
     let pipeline = test_pipeline();
     let mut theme = Theme::Dark;
     let mut run = Some(fresh_run(&pipeline));
@@ -159,13 +173,36 @@ fn stage_started_unknown_stage_is_noop() {
         &Some(pipeline),
         &mut run,
         &mut theme,
-        Msg::StageStarted {
+        Msg::TaskStarted {
             stage_name: "nonexistent".into(),
+            task_name: "compile".into(),
         },
     );
     assert_eq!(run, before);
 }
 
+#[test]
+fn task_result_skipped() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+
+    update_state(
+        &Some(pipeline),
+        &mut run,
+        &mut theme,
+        Msg::TaskResult {
+            stage_name: "test".into(),
+            task_name: "integration".into(),
+            state: TaskState::Skipped,
+            result: TaskResult::default(),
+        },
+    );
+
+    let r = run.unwrap();
+    assert_eq!(r.stages[1].tasks[1].state, TaskState::Skipped);
+}
+
 #[test]
 fn task_result_pass() {
     let pipeline = test_pipeline();
@@ -179,12 +216,27 @@ fn task_result_pass() {
         Msg::TaskResult {
             stage_name: "build".into(),
             task_name: "compile".into(),
-            passed: true,
+            state: TaskState::Pass,
+            result: TaskResult {
+                started_at: Some(100),
+                finished_at: Some(103),
+                duration_ms: Some(2_750),
+                exit_code: Some(0),
+                oom_killed: false,
+                worker: Some("http://worker:9002".into()),
+                tests: vec![],
+                artifacts: vec![],
+            },
         },
     );
 
     let r = run.unwrap();
     assert_eq!(r.stages[0].tasks[0].state, TaskState::Pass);
+    assert_eq!(r.stages[0].tasks[0].result.duration_ms, Some(2_750));
+    assert_eq!(
+        r.stages[0].tasks[0].result.worker.as_deref(),
+        Some("http://worker:9002")
+    );
     assert_eq!(r.stages[0].tasks[1].state, TaskState::Pending);
 }
 
@@ -201,7 +253,8 @@ fn task_result_fail() {
         Msg::TaskResult {
             stage_name: "build".into(),
             task_name: "compile".into(),
-            passed: false,
+            state: TaskState::Fail,
+            result: TaskResult::default(),
         },
     );
 
@@ -209,6 +262,28 @@ fn task_result_fail() {
     assert_eq!(r.stages[0].tasks[0].state, TaskState::Fail);
 }
 
+#[test]
+fn task_result_timed_out() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+
+    update_state(
+        &Some(pipeline),
+        &mut run,
+        &mut theme,
+        Msg::TaskResult {
+            stage_name: "test".into(),
+            task_name: "unit".into(),
+            state: TaskState::TimedOut,
+            result: TaskResult::default(),
+        },
+    );
+
+    let r = run.unwrap();
+    assert_eq!(r.stages[1].tasks[0].state, TaskState::TimedOut);
+}
+
 #[test]
 fn task_result_unknown_task_is_noop() {
     let pipeline = test_pipeline();
@@ -223,12 +298,122 @@ fn task_result_unknown_task_is_noop() {
         Msg::TaskResult {
             stage_name: "build".into(),
             task_name: "ghost".into(),
-            passed: true,
+            state: TaskState::Pass,
+            result: TaskResult::default(),
         },
     );
     assert_eq!(run, before);
 }
 
+#[test]
+fn cancel_run_requests_cancel_of_active_run() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.run_id = 4;
+    state.active = true;
+    let mut run = Some(state);
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::CancelRun);
+    assert_eq!(result.cancel_run, Some(4));
+}
+
+#[test]
+fn cancel_run_finished_run_is_noop() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::CancelRun);
+    assert!(result.cancel_run.is_none());
+}
+
+#[test]
+fn run_cancelled_marks_unfinished_tasks() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+    {
+        let r = run.as_mut().unwrap();
+        r.stages[0].tasks[0].state = TaskState::Pass;
+        r.stages[0].tasks[1].state = TaskState::Running;
+    }
+
+    update_state(&Some(pipeline), &mut run, &mut theme, Msg::RunCancelled);
+
+    let r = run.unwrap();
+    assert_eq!(r.stages[0].tasks[0].state, TaskState::Pass);
+    assert_eq!(r.stages[0].tasks[1].state, TaskState::Cancelled);
+    for task in &r.stages[1].tasks {
+        assert_eq!(task.state, TaskState::Cancelled);
+    }
+}
+
+#[test]
+fn rerun_failed_requests_rerun() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.run_id = 7;
+    state.stages[1].tasks[0].state = TaskState::Fail;
+    let mut run = Some(state);
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::RerunFailed);
+    assert_eq!(result.rerun_failed, Some(7));
+    assert!(result.close_sse);
+    assert!(run.is_none());
+}
+
+#[test]
+fn rerun_failed_counts_timeouts() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.stages[0].tasks[1].state = TaskState::TimedOut;
+    let mut run = Some(state);
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::RerunFailed);
+    assert_eq!(result.rerun_failed, Some(0));
+}
+
+#[test]
+fn rerun_failed_without_failures_is_noop() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+    let before = run.clone();
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::RerunFailed);
+    assert!(result.rerun_failed.is_none());
+    assert_eq!(run, before);
+}
+
+#[test]
+fn rerun_failed_active_run_is_noop() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.active = true;
+    state.stages[0].tasks[0].state = TaskState::Fail;
+    let mut run = Some(state);
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::RerunFailed);
+    assert!(result.rerun_failed.is_none());
+    assert!(run.is_some());
+}
+
+#[test]
+fn run_done_marks_run_inactive() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.active = true;
+    let mut run = Some(state);
+
+    update_state(&Some(pipeline), &mut run, &mut theme, Msg::RunDone);
+    assert!(!run.unwrap().active);
+}
+
 #[test]
 fn run_done_signals_close_sse() {
     let mut theme = Theme::Dark;
@@ -237,6 +422,52 @@ fn run_done_signals_close_sse() {
     assert!(result.close_sse);
 }
 
+#[test]
+fn run_done_refreshes_runs() {
+    let mut theme = Theme::Dark;
+    let mut run = None;
+    let result = update_state(&None, &mut run, &mut theme, Msg::RunDone);
+    assert!(result.refresh_runs);
+}
+
+#[test]
+fn run_started_clears_queued() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut state = fresh_run(&pipeline);
+    state.active = true;
+    state.queued = true;
+    let mut run = Some(state);
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::RunStarted);
+    assert!(!run.unwrap().queued);
+    assert!(result.refresh_runs);
+}
+
+#[test]
+fn select_run_switches_sse() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::SelectRun(3));
+    assert!(run.is_none()); // state comes from SSE
+    assert!(result.close_sse);
+    assert_eq!(result.open_sse, Some(3));
+}
+
+#[test]
+fn select_current_run_is_noop() {
+    let pipeline = test_pipeline();
+    let mut theme = Theme::Dark;
+    let mut run = Some(fresh_run(&pipeline));
+
+    let result = update_state(&Some(pipeline), &mut run, &mut theme, Msg::SelectRun(0));
+    assert!(run.is_some());
+    assert!(!result.close_sse);
+    assert!(result.open_sse.is_none());
+}
+
 #[test]
 fn new_run_replaces_old() {
     let pipeline = test_pipeline();
@@ -302,8 +533,10 @@ fn state_loaded_with_active_run() {
         &mut run,
         &mut theme,
         Msg::StateLoaded(StateResponse {
-            pipeline: pipeline.clone(),
+            pipeline: Some(pipeline.clone()),
+            pipeline_error: None,
             run: Some(state.clone()),
+            runs: vec![],
         }),
     );
     assert_eq!(run, Some(state));
@@ -321,8 +554,10 @@ fn state_loaded_no_run() {
         &mut run,
         &mut theme,
         Msg::StateLoaded(StateResponse {
-            pipeline,
+            pipeline: Some(pipeline),
+            pipeline_error: None,
             run: None,
+            runs: vec![],
         }),
     );
     assert!(run.is_none());
@@ -342,8 +577,10 @@ fn state_loaded_finished_run() {
         &mut run,
         &mut theme,
         Msg::StateLoaded(StateResponse {
-            pipeline: pipeline.clone(),
+            pipeline: Some(pipeline.clone()),
+            pipeline_error: None,
             run: Some(state.clone()),
+            runs: vec![],
         }),
     );
     assert_eq!(run, Some(state));
@@ -365,6 +602,24 @@ fn render_task_pending() {
     );
 }
 
+#[test]
+fn render_task_with_duration() {
+    let mut t = task("compile", TaskState::Pass);
+    t.result.duration_ms = Some(12_345);
+    assert_eq!(
+        to_html(&view_task(&t, "build", 1, &Theme::Dark)),
+        r#"<li class="pass"><a href="/logs?run=1&stage=build&task=compile&theme=dark">compile</a><span class="duration">12.3s</span></li>"#,
+    );
+}
+
+#[test]
+fn format_durations() {
+    assert_eq!(format_duration(850), "850ms");
+    assert_eq!(format_duration(12_345), "12.3s");
+    assert_eq!(format_duration(245_000), "4m 05s");
+    assert_eq!(format_duration(3_725_000), "1h 02m");
+}
+
 #[test]
 fn render_task_running() {
     assert_eq!(
@@ -404,6 +659,45 @@ fn render_task_fail() {
     );
 }
 
+#[test]
+fn render_task_timed_out() {
+    assert_eq!(
+        to_html(&view_task(
+            &task("unit", TaskState::TimedOut),
+            "test",
+            1,
+            &Theme::Dark
+        )),
+        r#"<li class="timedout"><a href="/logs?run=1&stage=test&task=unit&theme=dark">unit</a></li>"#,
+    );
+}
+
+#[test]
+fn render_task_skipped() {
+    assert_eq!(
+        to_html(&view_task(
+            &task("unit", TaskState::Skipped),
+            "test",
+            1,
+            &Theme::Dark
+        )),
+        r#"<li class="skipped"><a href="/logs?run=1&stage=test&task=unit&theme=dark">unit</a></li>"#,
+    );
+}
+
+#[test]
+fn render_task_cancelled() {
+    assert_eq!(
+        to_html(&view_task(
+            &task("lint", TaskState::Cancelled),
+            "build",
+            1,
+            &Theme::Dark
+        )),
+        r#"<li class="cancelled"><a href="/logs?run=1&stage=build&task=lint&theme=dark">lint</a></li>"#,
+    );
+}
+
 #[test]
 fn render_stage() {
     let stage = StageRunState {
@@ -427,6 +721,166 @@ fn render_stage() {
     );
 }
 
+#[test]
+fn render_rerun_shows_parent() {
+    let run = PipelineRunState {
+        run_id: 2,
+        active: false,
+        started_at: None,
+        finished_at: None,
+        parent_run_id: Some(1),
+        queued: false,
+        git: None,
+        stages: vec![StageRunState {
+            name: "test".into(),
+            tasks: vec![task("unit", TaskState::Pass)],
+        }],
+    };
+    assert_eq!(
+        to_html(&view_run(&Some(run), &Theme::Dark)),
+        concat!(
+            r#"<p class="parent">rerun of failed tasks from #1</p>"#,
+            "<section>",
+            "<h2>test</h2>",
+            "<ul>",
+            r#"<li class="pass"><a href="/logs?run=2&stage=test&task=unit&theme=dark">unit</a></li>"#,
+            "</ul>",
+            "</section>",
+        ),
+    );
+}
+
+#[test]
+fn render_queued_run() {
+    let run = PipelineRunState {
+        run_id: 2,
+        active: true,
+        started_at: None,
+        finished_at: None,
+        parent_run_id: None,
+        queued: true,
+        git: None,
+        stages: vec![StageRunState {
+            name: "test".into(),
+            tasks: vec![task("unit", TaskState::Pending)],
+        }],
+    };
+    assert_eq!(
+        to_html(&view_run(&Some(run), &Theme::Dark)),
+        concat!(
+            r#"<p class="queued">queued, waiting for a free run slot</p>"#,
+            "<section>",
+            "<h2>test</h2>",
+            "<ul>",
+            r#"<li class="pending"><a href="/logs?run=2&stage=test&task=unit&theme=dark">unit</a></li>"#,
+            "</ul>",
+            "</section>",
+        ),
+    );
+}
+
+fn pipedef_error() -> PipedefErrorDto {
+    PipedefErrorDto {
+        summary: "SyntaxError: '(' was never closed".into(),
+        line: Some(33),
+        details: "pipedef parse failed:\nSyntaxError: '(' was never closed".into(),
+    }
+}
+
+#[test]
+fn render_pipeline_error() {
+    assert_eq!(
+        to_html(&view_pipeline_error(&Some(pipedef_error()), true)),
+        concat!(
+            r#"<div class="banner">"#,
+            "<p>pipedef broken, still running the last version that parsed</p>",
+            r#"<p class="summary">line 33: SyntaxError: '(' was never closed</p>"#,
+            "<pre>pipedef parse failed:\nSyntaxError: '(' was never closed</pre>",
+            "</div>",
+        ),
+    );
+}
+
+#[test]
+fn render_pipeline_error_without_pipeline() {
+    let mut error = pipedef_error();
+    error.line = None;
+    assert_eq!(
+        to_html(&view_pipeline_error(&Some(error), false)),
+        concat!(
+            r#"<div class="banner">"#,
+            "<p>pipedef broken, nothing can run until it's fixed</p>",
+            r#"<p class="summary">SyntaxError: '(' was never closed</p>"#,
+            "<pre>pipedef parse failed:\nSyntaxError: '(' was never closed</pre>",
+            "</div>",
+        ),
+    );
+}
+
+#[test]
+fn render_no_pipeline_error_is_empty() {
+    assert_eq!(to_html(&view_pipeline_error(&None, true)), "");
+}
+
+#[test]
+fn render_commit() {
+    let mut run = fresh_run(&test_pipeline());
+    run.git = Some(GitCheckout {
+        repo: "https://example.com/repo.git".into(),
+        git_ref: "main".into(),
+        sha: "0123456789abcdef0123456789abcdef01234567".into(),
+    });
+    assert_eq!(
+        to_html(&view_commit(&Some(run))),
+        concat!(
+            r#"<span title="0123456789abcdef0123456789abcdef01234567" class="commit">"#,
+            "https://example.com/repo.git @ main (0123456)",
+            "</span>",
+        ),
+    );
+}
+
+#[test]
+fn render_commit_without_git_is_empty() {
+    let run = fresh_run(&test_pipeline());
+    assert_eq!(to_html(&view_commit(&Some(run))), "");
+    assert_eq!(to_html(&view_commit(&None)), "");
+}
+
+fn summary(run_id: u32, queued: bool) -> RunSummary {
+    RunSummary {
+        run_id,
+        active: true,
+        queued,
+        started_at: None,
+        finished_at: None,
+        parent_run_id: None,
+        passed: 0,
+        failed: 0,
+        total: 1,
+    }
+}
+
+#[test]
+fn render_runs_lists_active_runs() {
+    let runs = vec![summary(1, false), summary(2, true)];
+    assert_eq!(
+        to_html(&view_runs(&runs, Some(1), Callback::noop())),
+        concat!(
+            r#"<nav class="runs">"#,
+            r#"<button class="selected">#1 running</button>"#,
+            "<button>#2 queued</button>",
+            "</nav>",
+        ),
+    );
+}
+
+#[test]
+fn render_runs_single_run_is_empty() {
+    let runs = vec![summary(1, false)];
+    assert_eq!(to_html(&view_runs(&runs, Some(1), Callback::noop())), "");
+}
+
 #[test]
 fn render_run_none_is_empty() {
     assert_eq!(to_html(&view_run(&None, &Theme::Dark)), "");
@@ -437,6 +891,11 @@ fn render_run_multiple_stages() {
     let run = PipelineRunState {
         run_id: 1,
         active: false,
+        started_at: None,
+        finished_at: None,
+        parent_run_id: None,
+        queued: false,
+        git: None,
         stages: vec![
             StageRunState {
                 name: "build".into(),
@@ -473,10 +932,12 @@ fn render_run_multiple_stages() {
 fn render_logs_page_empty() {
     assert_eq!(
         to_html(&view_logs_page(
+            1,
             "build",
             "compile",
             &Theme::Dark,
             "",
+            &TaskResult::default(),
             Callback::noop()
         )),
         concat!(
@@ -495,10 +956,12 @@ fn render_logs_page_empty() {
 fn render_logs_page_with_output() {
     assert_eq!(
         to_html(&view_logs_page(
+            1,
             "build",
             "compile",
             &Theme::Dark,
             "line one\nline two",
+            &TaskResult::default(),
             Callback::noop()
         )),
         concat!(
@@ -519,10 +982,12 @@ fn render_logs_page_with_output() {
 fn render_logs_page_light() {
     assert_eq!(
         to_html(&view_logs_page(
+            1,
             "build",
             "compile",
             &Theme::Light,
             "",
+            &TaskResult::default(),
             Callback::noop()
         )),
         concat!(
@@ -537,6 +1002,89 @@ fn render_logs_page_light() {
     );
 }
 
+fn test_case(name: &str, status: TestStatus, message: Option<&str>) -> TestCase {
+    TestCase {
+        class: "test.test_models".into(),
+        name: name.into(),
+        status,
+        duration_ms: Some(1_500),
+        message: message.map(String::from),
+    }
+}
+
+#[test]
+fn render_tests_failures_first() {
+    let tests = vec![
+        test_case("test_create", TestStatus::Passed, None),
+        test_case("test_later", TestStatus::Skipped, Some("not yet")),
+        test_case("test_delete", TestStatus::Failed, Some("assert 1 == 2")),
+    ];
+    assert_eq!(
+        to_html(&view_tests(&tests)),
+        concat!(
+            r#"<section class="tests">"#,
+            "<h2>3 tests: 1 passed, 1 failed, 1 skipped</h2>",
+            "<ul>",
+            r#"<li class="fail">test.test_models.test_delete<span class="duration">1.5s</span><pre>assert 1 == 2</pre></li>"#,
+            r#"<li class="skipped">test.test_models.test_later<span class="duration">1.5s</span><pre>not yet</pre></li>"#,
+            r#"<li class="pass">test.test_models.test_create<span class="duration">1.5s</span></li>"#,
+            "</ul>",
+            "</section>",
+        ),
+    );
+}
+
+#[test]
+fn render_tests_empty() {
+    assert_eq!(to_html(&view_tests(&[])), "");
+}
+
+#[test]
+fn set_result_replaces_tests() {
+    let mut theme = Theme::Dark;
+    let mut output = String::new();
+    let mut result = TaskResult {
+        tests: vec![test_case("old", TestStatus::Passed, None)],
+        ..Default::default()
+    };
+    let changed = update_logs(
+        &mut theme,
+        &mut output,
+        &mut result,
+        LogsMsg::SetResult(TaskResult {
+            tests: vec![test_case("new", TestStatus::Error, None)],
+            artifacts: vec!["coverage.xml".into()],
+            ..Default::default()
+        }),
+    );
+    assert!(changed);
+    assert_eq!(result.tests.len(), 1);
+    assert_eq!(result.tests[0].name, "new");
+    assert_eq!(result.artifacts, vec!["coverage.xml"]);
+}
+
+#[test]
+fn render_artifact_links() {
+    assert_eq!(
+        to_html(&view_artifacts(
+            7,
+            "Unit",
+            "task_a",
+            &["htmlcov/index.html".into(), "dist/my app#1.whl".into()],
+        )),
+        concat!(
+            r#"<section class="artifacts">"#,
+            "<h2>2 artifacts</h2>",
+            "<ul>",
+            r#"<li><a href="/api/run/7/artifacts/Unit.task_a/htmlcov/index.html" download="">htmlcov/index.html</a></li>"#,
+            r#"<li><a href="/api/run/7/artifacts/Unit.task_a/dist/my%20app%231.whl" download="">dist/my app#1.whl</a></li>"#,
+            "</ul>",
+            "</section>",
+        ),
+    );
+    assert_eq!(to_html(&view_artifacts(7, "Unit", "task_a", &[])), "");
+}
+
 #[test]
 fn set_output_replaces_content() {
     let mut theme = Theme::Dark;
@@ -544,12 +1092,14 @@ fn set_output_replaces_content() {
     update_logs(
         &mut theme,
         &mut output,
+        &mut TaskResult::default(),
         LogsMsg::SetOutput("hello\nworld".into()),
     );
     assert_eq!(output, "hello\nworld");
     update_logs(
         &mut theme,
         &mut output,
+        &mut TaskResult::default(),
         LogsMsg::SetOutput("replaced".into()),
     );
     assert_eq!(output, "replaced");
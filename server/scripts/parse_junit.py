@@ -0,0 +1,26 @@
+import json, sys
+import xml.etree.ElementTree as ET
+
+# a JUnit XML report on stdin -> json list of its test cases on stdout. takes
+# <testsuites> or a bare <testsuite>, nested however deep
+
+OUTCOMES = {'failure': 'failed', 'error': 'error', 'skipped': 'skipped'}
+
+root = ET.fromstring(sys.stdin.read())
+cases = []
+for case in root.iter('testcase'):
+    status, message = 'passed', None
+    for tag, outcome in OUTCOMES.items():
+        el = case.find(tag)
+        if el is not None:
+            status = outcome
+            parts = [p for p in (el.get('message'), (el.text or '').strip()) if p]
+            message = '\n'.join(parts) or None
+            break
+    try:
+        duration_ms = round(float(case.get('time')) * 1000)
+    except (TypeError, ValueError):
+        duration_ms = None
+    cases.append({'class': case.get('classname', ''), 'name': case.get('name', ''),
+                  'status': status, 'duration_ms': duration_ms, 'message': message})
+print(json.dumps(cases))
@@ -1,13 +1,145 @@
 import ast, json, sys
 
-tree = ast.parse(open(sys.argv[1]).read())
+
+def const_bool(node):
+    if isinstance(node, ast.Constant) and isinstance(node.value, bool):
+        return node.value
+    return None
+
+
+def fail_fast_assign(body):
+    # `fail_fast = True` / `FAIL_FAST = True` at the top of a class or module
+    for n in body:
+        if isinstance(n, ast.Assign) and len(n.targets) == 1:
+            t = n.targets[0]
+            if isinstance(t, ast.Name) and t.id.lower() == 'fail_fast':
+                return const_bool(n.value)
+    return None
+
+
+def needs_refs(decorators, stage, own_tasks, stage_names):
+    # @needs(OtherStage, OtherStage.task_x, task_in_this_stage) -> [(stage, task or None, line)]
+    # None when there's no @needs at all, so "needs nothing" stays distinguishable
+    refs = None
+    for d in decorators:
+        if not (isinstance(d, ast.Call) and isinstance(d.func, ast.Name)
+                and d.func.id == 'needs'):
+            continue
+        refs = refs or []
+        for a in d.args:
+            if isinstance(a, ast.Name) and a.id in stage_names:
+                refs.append((a.id, None, a.lineno))
+            elif isinstance(a, ast.Name) and a.id in own_tasks:
+                refs.append((stage, a.id, a.lineno))
+            elif isinstance(a, ast.Attribute) and isinstance(a.value, ast.Name):
+                refs.append((a.value.id, a.attr, a.lineno))
+            else:
+                sys.exit(f"line {a.lineno}: @needs takes stage classes or task methods")
+    return refs
+
+
+def find_cycle(stages):
+    # every task's deps as (stage, task, line of the @needs), or the whole
+    # previous stage for tasks without @needs. None if nothing waits on itself
+    deps = {}
+    prev = None
+    for s in stages:
+        for t in s['tasks']:
+            implicit = [(prev['name'], p, None) for p in prev['tasks']] if prev else []
+            deps[(s['name'], t)] = s['needs'].get(t, implicit)
+        prev = s
+    visiting, done = [], set()
+
+    def visit(node):
+        visiting.append(node)
+        for st, tk, line in deps[node]:
+            dep = (st, tk)
+            if dep in visiting:
+                return visiting[visiting.index(dep):] + [dep], line
+            if dep not in done:
+                found = visit(dep)
+                if found:
+                    cycle, blamed = found
+                    return cycle, blamed or line
+        visiting.pop()
+        done.add(node)
+        return None
+
+    for node in deps:
+        if node not in done:
+            found = visit(node)
+            if found:
+                return found
+    return None
+
+
+def required_labels(decorators):
+    # @requires(mem="high", gpu=True) -> {'mem': 'high', 'gpu': 'true'}
+    labels = {}
+    for d in decorators:
+        if not (isinstance(d, ast.Call) and isinstance(d.func, ast.Name)
+                and d.func.id == 'requires'):
+            continue
+        if d.args:
+            sys.exit(f"line {d.lineno}: @requires takes labels as key=value arguments")
+        for kw in d.keywords:
+            v = kw.value
+            if not (isinstance(v, ast.Constant) and isinstance(v.value, (str, bool, int))):
+                sys.exit(f"line {v.lineno}: @requires labels have to be plain strings")
+            labels[kw.arg] = str(v.value).lower() if isinstance(v.value, bool) else str(v.value)
+    return labels
+
+
+tree = ast.parse(sys.stdin.read(), sys.argv[1])
+classes = [node for node in tree.body
+           if isinstance(node, ast.ClassDef)
+           and 'Stage' in [b.id if isinstance(b, ast.Name) else '' for b in node.bases]]
+stage_names = {c.name for c in classes}
+
 stages = []
-for node in tree.body:
-    if isinstance(node, ast.ClassDef):
-        bases = [b.id if isinstance(b, ast.Name) else '' for b in node.bases]
-        if 'Stage' in bases:
-            tasks = [n.name for n in node.body
-                     if isinstance(n, (ast.FunctionDef, ast.AsyncFunctionDef))
-                     and n.name.startswith('task_')]
-            stages.append({'name': node.name, 'tasks': tasks})
-print(json.dumps(stages))
+for node in classes:
+    funcs = [n for n in node.body
+             if isinstance(n, (ast.FunctionDef, ast.AsyncFunctionDef))
+             and n.name.startswith('task_')]
+    tasks = [f.name for f in funcs]
+    stage_refs = needs_refs(node.decorator_list, node.name, tasks, stage_names)
+    task_refs = {}
+    stage_labels = required_labels(node.decorator_list)
+    task_labels = {}
+    for f in funcs:
+        labels = {**stage_labels, **required_labels(f.decorator_list)}
+        if labels:
+            task_labels[f.name] = labels
+        refs = needs_refs(f.decorator_list, node.name, tasks, stage_names)
+        if stage_refs is not None or refs is not None:
+            task_refs[f.name] = (stage_refs or []) + (refs or [])
+    stages.append({'name': node.name, 'tasks': tasks,
+                   'fail_fast': fail_fast_assign(node.body), 'refs': task_refs,
+                   'labels': task_labels})
+
+# expand whole-stage refs into that stage's tasks, and make sure the rest exist
+tasks_of = {s['name']: s['tasks'] for s in stages}
+for s in stages:
+    needs = {}
+    for task, refs in s.pop('refs').items():
+        needs[task] = []
+        for st, tk, line in refs:
+            if tk is None:
+                needs[task] += [(st, t, line) for t in tasks_of[st] if (st, t) != (s['name'], task)]
+            elif tk in tasks_of.get(st, []):
+                needs[task].append((st, tk, line))
+            else:
+                sys.exit(f"line {line}: @needs({st}.{tk}): there's no such task")
+    s['needs'] = needs
+
+found = find_cycle(stages)
+if found:
+    cycle, line = found
+    path = ' -> '.join(f"{st}.{tk}" for st, tk in cycle)
+    sys.exit(f"line {line}: @needs makes a cycle, {path}")
+
+for s in stages:
+    s['needs'] = {task: [{'stage': st, 'task': tk} for st, tk, _ in refs]
+                  for task, refs in s['needs'].items()}
+print(json.dumps({'stages': stages,
+                  'fail_fast': fail_fast_assign(tree.body) or False}))
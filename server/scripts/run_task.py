@@ -1,9 +1,26 @@
-import sys, importlib.util, pathlib, os
+import sys, importlib.util, os, signal, threading
 
-# args: bettertest_lib_dir, pipedef_path, stage_class_name, task_method_name
+# turn SIGTERM into SystemExit instead of dying outright, so bettertest.run()
+# can clean up its container on the way out
+signal.signal(signal.SIGTERM, lambda *_: sys.exit(143))
+
+
+# the boss closes our stdin to cancel the task. a signal to ourselves
+# interrupts whatever the main thread is blocked on
+def _watch_stdin():
+    sys.stdin.read()
+    os.kill(os.getpid(), signal.SIGTERM)
+
+
+threading.Thread(target=_watch_stdin, daemon=True).start()
+
+# args: bettertest_lib_dir, pipedef_path, pipedef_dir, stage_class_name, task_method_name
+# pipedef_path may be the boss's copy for the run; its imports resolve from pipedef_dir
 sys.path.insert(0, sys.argv[1])
 pipedef_path = sys.argv[2]
-sys.path.insert(0, str(pathlib.Path(pipedef_path).parent))
+sys.path.insert(0, sys.argv[3])
+# don't leave a __pycache__ next to the copy, which sits among the run's artifacts
+sys.dont_write_bytecode = True
 
 if not os.path.isfile(pipedef_path):
     print(f"ERROR: pipedef not found: {pipedef_path}", file=sys.stderr)
@@ -16,4 +33,4 @@ if spec is None:
 
 mod = importlib.util.module_from_spec(spec)
 spec.loader.exec_module(mod)
-getattr(getattr(mod, sys.argv[3]), sys.argv[4])()
+getattr(getattr(mod, sys.argv[4]), sys.argv[5])()
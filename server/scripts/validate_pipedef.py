@@ -0,0 +1,80 @@
+import ast, json, sys
+
+# mistakes that parse fine but don't do what the author meant. prints one
+# {"line", "message"} per problem
+
+
+def base_names(node):
+    return [b.id for b in node.bases if isinstance(b, ast.Name)]
+
+
+def task_funcs(node):
+    return [n for n in node.body
+            if isinstance(n, (ast.FunctionDef, ast.AsyncFunctionDef))
+            and n.name.startswith('task_')]
+
+
+def decorator_names(f):
+    return [d.id for d in f.decorator_list if isinstance(d, ast.Name)]
+
+
+tree = ast.parse(sys.stdin.read(), sys.argv[1])
+problems = []
+
+
+def problem(node, message):
+    problems.append({'line': node.lineno, 'message': message})
+
+
+# every stage definition, with the stages it subclasses. a base has to be
+# defined before the class using it, so `stages` always has it by then
+stages = {}
+definitions = []
+for node in tree.body:
+    if not isinstance(node, ast.ClassDef):
+        continue
+    bases = base_names(node)
+    if 'Stage' not in bases and any(b in stages for b in bases):
+        # the boss only picks up classes that list Stage themselves
+        problem(node, f"{node.name} subclasses a stage but not Stage itself, so it never runs "
+                      f"(use `class {node.name}({', '.join(bases)}, Stage)`)")
+    if 'Stage' in bases or any(b in stages for b in bases):
+        if node.name in stages:
+            problem(node, f"stage {node.name} is defined twice, only the last one runs")
+        definitions.append((node, [stages[b] for b in bases if b in stages]))
+        stages[node.name] = node
+
+for node, parents in definitions:
+    name = node.name
+    tasks = task_funcs(node)
+
+    if all(isinstance(n, ast.Pass) or (isinstance(n, ast.Expr) and isinstance(n.value, ast.Constant))
+           for n in node.body):
+        problem(node, f"stage {name} is empty")
+    elif not tasks:
+        problem(node, f"stage {name} has no task_ methods, so it never runs anything")
+
+    seen = {}
+    for f in tasks:
+        if f.name in seen:
+            problem(f, f"{name}.{f.name} is defined twice (first on line {seen[f.name]}), "
+                       "only the last one runs")
+        seen[f.name] = f.lineno
+
+        args = f.args
+        if 'classmethod' in decorator_names(f):
+            problem(f, f"{name}.{f.name} is a classmethod, tasks must be static")
+        elif args.posonlyargs or args.args or args.vararg or args.kwonlyargs or args.kwarg:
+            problem(f, f"{name}.{f.name} takes arguments, but tasks are called with none "
+                       "(drop `self`)")
+
+    # a stage subclassing another stage: the boss only reads the subclass's own
+    # body, so a task with the same name as a parent's replaces it in the subclass
+    for parent in parents:
+        parent_tasks = {f.name for f in task_funcs(parent)}
+        for f in tasks:
+            if f.name in parent_tasks:
+                problem(f, f"{name}.{f.name} shadows {parent.name}.{f.name}")
+
+for p in sorted(problems, key=lambda p: p['line']):
+    print(json.dumps(p))
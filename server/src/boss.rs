@@ -1,355 +1,647 @@
+use crate::pipedef;
+use crate::pool::{self, WorkerPool};
+use crate::scheduler::{self, ActiveRun, SseEvent, TaskCtx, initial_run_state, persist};
+use crate::secrets::Secrets;
+use crate::status::{CommitStatus, StatusReporter, StatusState};
+use crate::store::{RunStore, now_secs};
+use crate::webhook;
 use axum::{
     Json, Router,
+    body::Bytes,
     extract::{Path as AxumPath, State},
-    http::{StatusCode, header},
+    http::{HeaderMap, StatusCode, header},
     response::{
         IntoResponse, Response,
-        sse::{Event, Sse},
+        sse::{Event, KeepAlive, Sse},
     },
     routing::{get, post},
 };
 use bettertest_common::*;
 use rust_embed::Embed;
-use serde::Serialize;
 use std::convert::Infallible;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use tokio::io::AsyncBufReadExt;
-use tokio::sync::{Mutex, broadcast};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore, broadcast};
 
 #[derive(Embed)]
 #[folder = "../frontend/dist/"]
 struct Assets;
 
-#[derive(Clone)]
-struct SseEvent {
-    event: String,
-    data: String,
-}
-
-struct ActiveRun {
-    run_id: u32,
-    state: Mutex<PipelineRunState>,
-    tx: broadcast::Sender<SseEvent>,
-    active: AtomicBool,
-}
-
 pub(crate) struct BossState {
-    pipeline: PipelineDto,
+    /// swapped whenever the pipedef changes. runs take a copy when they're created.
+    /// None until the pipedef parses for the first time
+    pipedef: std::sync::RwLock<Option<Pipedef>>,
+    /// why the pipedef on disk doesn't parse. the previous pipeline stays in use
+    pipeline_error: std::sync::RwLock<Option<PipedefErrorDto>>,
+    /// boss-wide events (pipeline reloads) for every connected frontend
+    events: broadcast::Sender<SseEvent>,
     pipedef_path: PathBuf,
     bettertest_lib_dir: PathBuf,
     run_counter: AtomicU32,
-    active_run: Mutex<Option<Arc<ActiveRun>>>,
+    /// queued and running runs, plus the most recent one even once it's done
+    runs: Mutex<Vec<Arc<ActiveRun>>>,
+    /// one permit per run allowed to execute at the same time
+    run_slots: Arc<Semaphore>,
+    store: RunStore,
+    secrets: Arc<Secrets>,
+    /// key the webhooks' HMAC signatures are checked against. None disables them
+    webhook_secret: Option<String>,
+    /// from --repo: the only repos runs may check out
+    repos: Vec<String>,
+    /// where commit statuses for git runs go, if anywhere
+    reporter: Option<Arc<dyn StatusReporter>>,
+    /// base of the run links in commit statuses
+    public_url: String,
+    /// from --workers. None means pipedefs have to name their workers' urls
+    pool: Option<Arc<WorkerPool>>,
+    worker_token: Option<String>,
 }
 
-#[derive(Serialize)]
-struct StageStartedEvent<'a> {
-    stage: &'a str,
+/// a pipeline and the exact text it was parsed from. each run's tasks run
+/// against a copy of that text, so edits don't reach runs that already started
+#[derive(Clone)]
+struct Pipedef {
+    pipeline: PipelineDto,
+    source: Arc<str>,
 }
 
-#[derive(Serialize)]
-struct TaskResultEvent<'a> {
-    stage: &'a str,
-    task: &'a str,
-    passed: bool,
-    output: &'a str,
-}
+impl BossState {
+    fn pipeline(&self) -> Option<PipelineDto> {
+        self.pipedef().map(|p| p.pipeline)
+    }
 
-#[derive(Serialize)]
-struct TaskOutputEvent<'a> {
-    stage: &'a str,
-    task: &'a str,
-    line: &'a str,
-}
+    fn pipedef(&self) -> Option<Pipedef> {
+        self.pipedef.read().unwrap().clone()
+    }
+
+    fn set_pipeline(&self, parsed: Result<Pipedef, pipedef::PipedefError>) {
+        match parsed {
+            Ok(pipedef) => {
+                *self.pipedef.write().unwrap() = Some(pipedef);
+                *self.pipeline_error.write().unwrap() = None;
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                *self.pipeline_error.write().unwrap() = Some(e.to_dto());
+            }
+        }
+    }
 
-fn initial_run_state(pipeline: &PipelineDto, run_id: u32) -> PipelineRunState {
-    PipelineRunState {
-        run_id,
-        active: true,
-        stages: pipeline
-            .stages
+    async fn find_run(&self, run_id: u32) -> Option<Arc<ActiveRun>> {
+        self.runs
+            .lock()
+            .await
             .iter()
-            .map(|s| StageRunState {
-                name: s.name.clone(),
-                tasks: s
-                    .tasks
-                    .iter()
-                    .map(|t| TaskRunState {
-                        name: t.clone(),
-                        state: TaskState::Pending,
-                        output: String::new(),
-                    })
-                    .collect(),
-            })
-            .collect(),
+            .find(|r| r.run_id == run_id)
+            .cloned()
     }
 }
 
-fn parse_pipedef(path: &Path) -> PipelineDto {
-    let script = include_str!("../scripts/parse_pipedef.py");
-    let tmp = std::env::temp_dir().join("bettertest_parse_pipedef.py");
-    std::fs::write(&tmp, script).expect("failed to write parser script to temp file");
-
-    let output = std::process::Command::new("python3")
-        .arg(&tmp)
-        .arg(path)
-        .output()
-        .expect("failed to run python3 - is it installed?");
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        panic!("pipedef parse failed:\n{stderr}");
-    }
+pub struct BossOptions {
+    pub data_dir: Option<PathBuf>,
+    pub secrets: Option<PathBuf>,
+    pub max_concurrent_runs: usize,
+    pub webhook_secret: Option<PathBuf>,
+    pub repos: Vec<String>,
+    pub status_url: Option<String>,
+    pub status_token: Option<PathBuf>,
+    pub public_url: String,
+    pub workers: Option<PathBuf>,
+    pub worker_token: Option<String>,
+}
 
-    let stdout = String::from_utf8(output.stdout).expect("python output wasn't utf8");
-    let stages: Vec<StageDto> = serde_json::from_str(&stdout)
-        .unwrap_or_else(|e| panic!("failed to parse pipedef json: {e}\nraw output: {stdout}"));
+/// how often the pipedef's mtime is checked for edits
+const PIPEDEF_POLL: Duration = Duration::from_secs(1);
 
-    PipelineDto { stages }
+/// read the pipedef once and parse exactly what was read
+fn load_pipedef(path: &Path) -> Result<Pipedef, pipedef::PipedefError> {
+    let source = pipedef::read(path)?;
+    let pipeline = pipedef::parse_source(path, &source)?;
+    Ok(Pipedef {
+        pipeline,
+        source: source.into(),
+    })
 }
 
-fn setup_lib_dir() -> PathBuf {
-    let dir = std::env::temp_dir().join("bettertest_lib");
-    std::fs::create_dir_all(&dir).expect("failed to create bettertest lib dir");
-    std::fs::write(
-        dir.join("bettertest.py"),
-        include_str!("../../bettertest/__init__.py"),
-    )
-    .expect("failed to write bettertest.py");
-    std::fs::write(
-        dir.join("run_task.py"),
-        include_str!("../scripts/run_task.py"),
-    )
-    .expect("failed to write run_task.py");
-    dir
+/// re-parse the pipedef whenever it changes. in-flight runs keep the pipeline
+/// and the copy of the pipedef they started with; a pipedef that no longer
+/// parses leaves the old one in place
+async fn watch_pipedef(state: Arc<BossState>) {
+    let modified = |path: &Path| {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+    };
+    let mut last = modified(&state.pipedef_path);
+    loop {
+        tokio::time::sleep(PIPEDEF_POLL).await;
+        let now = modified(&state.pipedef_path);
+        if now == last {
+            continue;
+        }
+        last = now;
+
+        let path = state.pipedef_path.clone();
+        let Ok(parsed) = tokio::task::spawn_blocking(move || load_pipedef(&path)).await else {
+            continue;
+        };
+        if let Ok(pipedef) = &parsed {
+            println!("pipedef reloaded: {} stages", pipedef.pipeline.stages.len());
+        }
+        state.set_pipeline(parsed);
+        let event = match &*state.pipeline_error.read().unwrap() {
+            Some(e) => SseEvent {
+                event: "pipeline_error".into(),
+                data: serde_json::to_string(e).unwrap(),
+            },
+            None => SseEvent {
+                event: "pipeline_changed".into(),
+                data: serde_json::to_string(&state.pipeline()).unwrap(),
+            },
+        };
+        let _ = state.events.send(event);
+    }
 }
 
 async fn get_state(State(state): State<Arc<BossState>>) -> Json<StateResponse> {
-    let run = {
-        let guard = state.active_run.lock().await;
-        match guard.as_ref() {
-            Some(active) => {
-                let st = active.state.lock().await;
-                let mut snapshot = st.clone();
-                snapshot.active = active.active.load(Ordering::Relaxed);
-                Some(snapshot)
-            }
-            None => None,
+    let live: Vec<_> = state.runs.lock().await.clone();
+    let mut runs = vec![];
+    for r in &live {
+        let snapshot = r.snapshot().await;
+        if snapshot.active {
+            runs.push(RunSummary::of(&snapshot));
         }
+    }
+    let run = match live.last() {
+        Some(latest) => Some(latest.snapshot().await),
+        None => None,
     };
     Json(StateResponse {
-        pipeline: state.pipeline.clone(),
+        pipeline: state.pipeline(),
+        pipeline_error: state.pipeline_error.read().unwrap().clone(),
         run,
+        runs,
     })
 }
 
-#[derive(Serialize)]
-pub struct RunCreated {
-    run_id: u32,
+/// without a body the run tests whatever the image was built from. picking
+/// the commit to run is as good as running code, so a body has to be signed
+/// like the generic webhook
+async fn create_run(
+    State(state): State<Arc<BossState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<RunCreated>, (StatusCode, String)> {
+    let git = if body.is_empty() {
+        None
+    } else {
+        check_signature(&state, &headers, webhook::GENERIC_SIGNATURE, &body)?;
+        let req: CreateRunRequest =
+            serde_json::from_slice(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        Some(checkout(&state, req.repo, req.git_ref, None).await?)
+    };
+    let pipedef = state.pipedef().ok_or_else(pipeline_broken)?;
+    let run_id = start_run(&state, pipedef, None, vec![], git).await;
+    Ok(Json(RunCreated { run_id }))
 }
 
-async fn create_run(State(state): State<Arc<BossState>>) -> Json<RunCreated> {
+fn pipeline_broken() -> (StatusCode, String) {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        "the pipedef doesn't parse, see pipeline_error in /api/state".into(),
+    )
+}
+
+/// resolves `git_ref` unless the caller already knows the sha. only repos
+/// from --repo, since tasks run whatever they check out with the boss's secrets
+async fn checkout(
+    state: &BossState,
+    repo: String,
+    git_ref: String,
+    sha: Option<String>,
+) -> Result<GitCheckout, (StatusCode, String)> {
+    let Some(repo) = allowed_repo(&state.repos, &repo) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{repo} isn't one of the boss's --repo"),
+        ));
+    };
+    let sha = match sha {
+        Some(sha) if crate::git::is_sha(&sha) => sha.to_lowercase(),
+        Some(sha) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("{sha} isn't a full commit sha"),
+            ));
+        }
+        None => crate::git::resolve(&repo, &git_ref)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+    };
+    Ok(GitCheckout { repo, git_ref, sha })
+}
+
+/// the --repo entry `repo` names, ignoring a trailing `/` or `.git`
+fn allowed_repo(repos: &[String], repo: &str) -> Option<String> {
+    let normalize = |r: &str| {
+        let r = r.trim_end_matches('/');
+        r.strip_suffix(".git").unwrap_or(r).to_string()
+    };
+    repos
+        .iter()
+        .find(|r| normalize(r) == normalize(repo))
+        .cloned()
+}
+
+fn check_signature(
+    state: &BossState,
+    headers: &HeaderMap,
+    header_name: &str,
+    body: &[u8],
+) -> Result<(), (StatusCode, String)> {
+    let Some(secret) = &state.webhook_secret else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "signed requests are disabled, start the boss with --webhook-secret".into(),
+        ));
+    };
+    let signature = headers
+        .get(header_name)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !webhook::verify(secret, body, signature) {
+        return Err((StatusCode::UNAUTHORIZED, "bad signature".into()));
+    }
+    Ok(())
+}
+
+async fn start_push(
+    state: &BossState,
+    push: webhook::Push,
+) -> Result<Response, (StatusCode, String)> {
+    let pipedef = state.pipedef().ok_or_else(pipeline_broken)?;
+    let git = checkout(state, push.repo, push.git_ref, push.sha).await?;
+    let run_id = start_run(state, pipedef, None, vec![], Some(git)).await;
+    Ok(Json(RunCreated { run_id }).into_response())
+}
+
+/// github push webhook. pings and other event types are acknowledged and ignored
+async fn github_hook(
+    State(state): State<Arc<BossState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    check_signature(&state, &headers, webhook::GITHUB_SIGNATURE, &body)?;
+    let event = headers
+        .get("x-github-event")
+        .and_then(|v| v.to_str().ok());
+    if event != Some("push") {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    match webhook::parse_github_push(&body) {
+        Ok(Some(push)) => start_push(&state, push).await,
+        Ok(None) => Ok(StatusCode::NO_CONTENT.into_response()),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+/// `{"repo", "ref", "sha"?}` signed like github's, for other forges and scripts
+async fn generic_hook(
+    State(state): State<Arc<BossState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    check_signature(&state, &headers, webhook::GENERIC_SIGNATURE, &body)?;
+    let push =
+        webhook::parse_generic_push(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    start_push(&state, push).await
+}
+
+/// new run of only the Fail/TimedOut tasks of `run_id`, same stage order
+async fn rerun_failed(
+    State(state): State<Arc<BossState>>,
+    AxumPath(run_id): AxumPath<u32>,
+) -> Result<Json<RunCreated>, StatusCode> {
+    let parent = match state.find_run(run_id).await {
+        Some(r) if r.active.load(Ordering::Relaxed) => return Err(StatusCode::CONFLICT),
+        Some(r) => r.snapshot().await,
+        None => state
+            .store
+            .get(run_id)
+            .ok_or(StatusCode::NOT_FOUND)?,
+    };
+
+    let Pipedef {
+        pipeline: current,
+        source,
+    } = state
+        .pipedef()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let stages: Vec<StageDto> = parent
+        .stages
+        .iter()
+        .map(|s| {
+            let config = current.stages.iter().find(|p| p.name == s.name);
+            StageDto {
+                name: s.name.clone(),
+                tasks: s
+                    .tasks
+                    .iter()
+                    .filter(|t| matches!(t.state, TaskState::Fail | TaskState::TimedOut))
+                    .map(|t| t.name.clone())
+                    .collect(),
+                fail_fast: config.and_then(|c| c.fail_fast),
+                needs: config
+                    .map(|c| c.needs.clone())
+                    .unwrap_or_default(),
+                labels: config
+                    .map(|c| c.labels.clone())
+                    .unwrap_or_default(),
+            }
+        })
+        .filter(|s| !s.tasks.is_empty())
+        .collect();
+    if stages.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let pipedef = Pipedef {
+        pipeline: PipelineDto {
+            stages,
+            fail_fast: current.fail_fast,
+        },
+        source,
+    };
+    // what passed before still counts for the @needs of what's rerun
+    let passed = parent
+        .stages
+        .iter()
+        .flat_map(|s| {
+            s.tasks
+                .iter()
+                .filter(|t| t.state == TaskState::Pass)
+                .map(|t| TaskId {
+                    stage: s.name.clone(),
+                    task: t.name.clone(),
+                })
+        })
+        .collect();
+    // same commit as the parent, not wherever its ref points now
+    let run_id = start_run(&state, pipedef, Some(run_id), passed, parent.git).await;
+    Ok(Json(RunCreated { run_id }))
+}
+
+async fn start_run(
+    state: &BossState,
+    Pipedef { pipeline, source }: Pipedef,
+    parent_run_id: Option<u32>,
+    excluded: Vec<TaskId>,
+    git: Option<GitCheckout>,
+) -> u32 {
     let run_id = state
         .run_counter
         .fetch_add(1, Ordering::Relaxed)
         + 1;
-    let (tx, _) = broadcast::channel::<SseEvent>(256);
-
-    let run_state = initial_run_state(&state.pipeline, run_id);
-    let active_run = Arc::new(ActiveRun {
-        run_id,
-        state: Mutex::new(run_state),
-        tx: tx.clone(),
-        active: AtomicBool::new(true),
-    });
+    let mut run_state = initial_run_state(&pipeline, run_id);
+    run_state.parent_run_id = parent_run_id;
+    run_state.git = git.clone();
+    let active_run = Arc::new(ActiveRun::new(run_state));
+
+    {
+        let mut runs = state.runs.lock().await;
+        runs.retain(|r| r.active.load(Ordering::Relaxed));
+        runs.push(active_run.clone());
+    }
+    let run_slots = state.run_slots.clone();
+
+    if let Some(reporter) = &state.reporter
+        && let Some(git) = &git
+    {
+        let target_url = format!("{}/?run={run_id}", state.public_url.trim_end_matches('/'));
+        tokio::spawn(report_statuses(
+            active_run.clone(),
+            active_run.tx.subscribe(),
+            reporter.clone(),
+            git.sha.clone(),
+            target_url,
+        ));
+    }
+
+    // the pipedef as it was when this run's pipeline was parsed, so a mid-run
+    // edit can't change or remove the tasks it still has to run
+    let run_dir = state.store.artifacts_dir(run_id);
+    let frozen = run_dir.join("pipedef.py");
+    let pipedef_path = std::fs::create_dir_all(&run_dir)
+        .and_then(|_| std::fs::write(&frozen, source.as_bytes()))
+        .map(|_| frozen)
+        .unwrap_or_else(|e| {
+            eprintln!("failed to copy the pipedef for run {run_id}, using the live one: {e}");
+            state.pipedef_path.clone()
+        });
 
-    *state.active_run.lock().await = Some(active_run.clone());
+    let ctx = TaskCtx {
+        pipedef_path,
+        pipedef_dir: state
+            .pipedef_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+        lib_dir: state.bettertest_lib_dir.clone(),
+        store: Some(state.store.clone()),
+        secrets: state.secrets.clone(),
+        git,
+        artifacts: Some(run_dir),
+        pool: state.pool.clone(),
+        worker_token: state.worker_token.clone(),
+    };
 
-    let pipeline = state.pipeline.clone();
-    let pipedef_path = state.pipedef_path.clone();
-    let lib_dir = state.bettertest_lib_dir.clone();
+    persist(&active_run, &state.store).await;
+    let store = state.store.clone();
 
     tokio::spawn(async move {
-        for stage in &pipeline.stages {
-            {
-                let mut st = active_run.state.lock().await;
-                if let Some(s) = st
-                    .stages
-                    .iter_mut()
-                    .find(|s| s.name == stage.name)
-                {
-                    for task in &mut s.tasks {
-                        task.state = TaskState::Running;
-                    }
-                }
+        // wait for a free slot. semaphore permits are handed out FIFO, so runs
+        // start in the order they were queued
+        let mut cancel_rx = active_run.cancel.subscribe();
+        // held until the run finishes
+        let permit = tokio::select! {
+            permit = run_slots.acquire_owned() => permit.ok(),
+            _ = cancel_rx.wait_for(|c| *c) => None,
+        };
+        {
+            let mut st = active_run.state.lock().await;
+            st.queued = false;
+            if permit.is_some() {
+                st.started_at = Some(now_secs());
             }
+        }
+        if permit.is_some() {
+            persist(&active_run, &store).await;
             let _ = active_run.tx.send(SseEvent {
-                event: "stage_started".into(),
-                data: serde_json::to_string(&StageStartedEvent { stage: &stage.name }).unwrap(),
+                event: "run_started".into(),
+                data: "{}".into(),
             });
+        }
 
-            let mut set = tokio::task::JoinSet::new();
-            for task in &stage.tasks {
-                let lib_dir = lib_dir.clone();
-                let pipedef_path = pipedef_path.clone();
-                let stage_name = stage.name.clone();
-                let task_name = task.clone();
-                let active_run = active_run.clone();
-
-                set.spawn(async move {
-                    let spawn_result = tokio::process::Command::new("python3")
-                        .arg("-u")
-                        .arg(lib_dir.join("run_task.py"))
-                        .arg(&lib_dir)
-                        .arg(&pipedef_path)
-                        .arg(&stage_name)
-                        .arg(&task_name)
-                        .stdout(std::process::Stdio::piped())
-                        .stderr(std::process::Stdio::piped())
-                        .spawn();
-
-                    let passed = match spawn_result {
-                        Ok(mut child) => {
-                            let (line_tx, mut line_rx) =
-                                tokio::sync::mpsc::unbounded_channel::<String>();
-
-                            let stdout = child.stdout.take().unwrap();
-                            let tx = line_tx.clone();
-                            tokio::spawn(async move {
-                                let mut lines = tokio::io::BufReader::new(stdout).lines();
-                                while let Ok(Some(line)) = lines.next_line().await {
-                                    let _ = tx.send(line);
-                                }
-                            });
-
-                            let stderr = child.stderr.take().unwrap();
-                            tokio::spawn(async move {
-                                let mut lines = tokio::io::BufReader::new(stderr).lines();
-                                while let Ok(Some(line)) = lines.next_line().await {
-                                    let _ = line_tx.send(line);
-                                }
-                            });
-
-                            while let Some(line) = line_rx.recv().await {
-                                {
-                                    let mut st = active_run.state.lock().await;
-                                    if let Some(s) = st
-                                        .stages
-                                        .iter_mut()
-                                        .find(|s| s.name == stage_name)
-                                        && let Some(t) =
-                                            s.tasks.iter_mut().find(|t| t.name == task_name)
-                                    {
-                                        if !t.output.is_empty() {
-                                            t.output.push('\n');
-                                        }
-                                        t.output.push_str(&line);
-                                    }
-                                }
-                                let _ = active_run.tx.send(SseEvent {
-                                    event: "task_output".into(),
-                                    data: serde_json::to_string(&TaskOutputEvent {
-                                        stage: &stage_name,
-                                        task: &task_name,
-                                        line: &line,
-                                    })
-                                    .unwrap(),
-                                });
-                            }
-
-                            child.wait().await.is_ok_and(|s| s.success())
-                        }
-                        Err(e) => {
-                            let mut st = active_run.state.lock().await;
-                            if let Some(s) = st
-                                .stages
-                                .iter_mut()
-                                .find(|s| s.name == stage_name)
-                                && let Some(t) = s.tasks.iter_mut().find(|t| t.name == task_name)
-                            {
-                                t.output = e.to_string();
-                            }
-                            false
-                        }
-                    };
-
-                    let output = {
-                        let st = active_run.state.lock().await;
-                        st.stages
-                            .iter()
-                            .find(|s| s.name == stage_name)
-                            .and_then(|s| s.tasks.iter().find(|t| t.name == task_name))
-                            .map(|t| t.output.clone())
-                            .unwrap_or_default()
-                    };
-
-                    {
-                        let mut st = active_run.state.lock().await;
-                        if let Some(s) = st
-                            .stages
-                            .iter_mut()
-                            .find(|s| s.name == stage_name)
-                            && let Some(t) = s.tasks.iter_mut().find(|t| t.name == task_name)
-                        {
-                            t.state = if passed {
-                                TaskState::Pass
-                            } else {
-                                TaskState::Fail
-                            };
-                        }
-                    }
-
-                    let _ = active_run.tx.send(SseEvent {
-                        event: "task_result".into(),
-                        data: serde_json::to_string(&TaskResultEvent {
-                            stage: &stage_name,
-                            task: &task_name,
-                            passed,
-                            output: &output,
-                        })
-                        .unwrap(),
-                    });
-                });
+        scheduler::run_pipeline(active_run, pipeline, excluded, ctx).await;
+        drop(permit);
+    });
+
+    run_id
+}
+
+/// mirrors a run onto its commit: pending as each stage starts, then the result.
+/// runs off the event stream, so a slow forge never holds up the run itself
+async fn report_statuses(
+    active_run: Arc<ActiveRun>,
+    mut events: broadcast::Receiver<SseEvent>,
+    reporter: Arc<dyn StatusReporter>,
+    sha: String,
+    target_url: String,
+) {
+    let status = |state, description| CommitStatus {
+        state,
+        target_url: target_url.clone(),
+        description,
+        context: "bettertest".into(),
+    };
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            // skipped some task output, nothing we care about
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        match event.event.as_str() {
+            "stage_started" => {
+                let stage = serde_json::from_str::<serde_json::Value>(&event.data)
+                    .ok()
+                    .and_then(|v| v["stage"].as_str().map(String::from))
+                    .unwrap_or_default();
+                let s = status(StatusState::Pending, format!("running {stage}"));
+                reporter.report(&sha, &s).await;
+            }
+            "run_done" => {
+                let (state, description) =
+                    crate::status::final_status(&active_run.snapshot().await);
+                reporter
+                    .report(&sha, &status(state, description))
+                    .await;
+                return;
             }
-            while set.join_next().await.is_some() {}
+            _ => {}
         }
+    }
+}
 
-        let _ = active_run.tx.send(SseEvent {
-            event: "run_done".into(),
-            data: "{}".into(),
-        });
-        active_run
-            .active
-            .store(false, Ordering::Relaxed);
-    });
+async fn cancel_run(
+    State(state): State<Arc<BossState>>,
+    AxumPath(run_id): AxumPath<u32>,
+) -> StatusCode {
+    match state.find_run(run_id).await {
+        None => StatusCode::NOT_FOUND,
+        Some(r) if !r.active.load(Ordering::Relaxed) => StatusCode::CONFLICT,
+        Some(r) => {
+            r.cancel.send_replace(true);
+            StatusCode::ACCEPTED
+        }
+    }
+}
+
+async fn list_runs(State(state): State<Arc<BossState>>) -> Json<Vec<RunSummary>> {
+    let mut runs = state.store.list();
+    // in-memory copies of live runs are fresher than what's on disk
+    let live: Vec<_> = state.runs.lock().await.clone();
+    for r in live {
+        let summary = RunSummary::of(&r.snapshot().await);
+        match runs
+            .iter_mut()
+            .find(|r| r.run_id == summary.run_id)
+        {
+            Some(r) => *r = summary,
+            None => runs.insert(0, summary),
+        }
+    }
+    runs.sort_unstable_by_key(|r| std::cmp::Reverse(r.run_id));
+    Json(runs)
+}
 
-    Json(RunCreated { run_id })
+async fn get_run(
+    State(state): State<Arc<BossState>>,
+    AxumPath(run_id): AxumPath<u32>,
+) -> Result<Json<PipelineRunState>, StatusCode> {
+    if let Some(active) = state.find_run(run_id).await {
+        return Ok(Json(active.snapshot().await));
+    }
+    state
+        .store
+        .get(run_id)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// a file a task collected with `run(artifacts=...)`. `path` starts with the
+/// task's `{stage}.{task}/`
+async fn get_artifact(
+    State(state): State<Arc<BossState>>,
+    AxumPath((run_id, path)): AxumPath<(u32, String)>,
+) -> Response {
+    let Some(relative) = artifact_path(&path) else {
+        return (StatusCode::NOT_FOUND, "Not found").into_response();
+    };
+    let file = state.store.artifacts_dir(run_id).join(relative);
+    match tokio::fs::read(&file).await {
+        Ok(data) => {
+            let mime = mime_guess::from_path(&file).first_or_octet_stream();
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, mime.as_ref())],
+                data,
+            )
+                .into_response()
+        }
+        Err(_) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+    }
+}
+
+/// a request path as a relative path that can't climb out of the run's artifacts
+fn artifact_path(path: &str) -> Option<&Path> {
+    let path = Path::new(path);
+    path.components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+        .then_some(path)
+}
+
+/// boss-wide events, as opposed to a single run's
+async fn boss_events(
+    State(state): State<Arc<BossState>>,
+) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
+    let mut rx = state.events.subscribe();
+    let stream = async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(sse) => yield Ok(Event::default().event(sse.event).data(sse.data)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+    Sse::new(stream).keep_alive(KeepAlive::default())
 }
 
 async fn run_events(
     State(state): State<Arc<BossState>>,
     AxumPath(run_id): AxumPath<u32>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, StatusCode> {
-    let active_run = {
-        let guard = state.active_run.lock().await;
-        match guard
-            .as_ref()
-            .filter(|r| r.run_id == run_id)
-            .cloned()
-        {
-            Some(r) => r,
-            None => return Err(StatusCode::NOT_FOUND),
-        }
+    let Some(active_run) = state.find_run(run_id).await else {
+        return Err(StatusCode::NOT_FOUND);
     };
 
     // subscribe BEFORE snapshot — no gap
     let mut rx = active_run.tx.subscribe();
 
-    let snapshot = {
-        let st = active_run.state.lock().await;
-        serde_json::to_string(&*st).unwrap()
-    };
+    let snapshot = serde_json::to_string(&active_run.snapshot().await).unwrap();
 
     let stream = async_stream::stream! {
         yield Ok(Event::default().event("state").data(snapshot));
@@ -362,10 +654,7 @@ async fn run_events(
                     if done { break; }
                 }
                 Err(broadcast::error::RecvError::Lagged(_)) => {
-                    let fresh = {
-                        let st = active_run.state.lock().await;
-                        serde_json::to_string(&*st).unwrap()
-                    };
+                    let fresh = serde_json::to_string(&active_run.snapshot().await).unwrap();
                     yield Ok(Event::default().event("state").data(fresh));
                 }
                 Err(broadcast::error::RecvError::Closed) => break,
@@ -410,7 +699,15 @@ fn api_routes() -> Router<Arc<BossState>> {
     Router::new()
         .route("/api/state", get(get_state))
         .route("/api/run", post(create_run))
+        .route("/api/runs", get(list_runs))
+        .route("/api/events", get(boss_events))
+        .route("/api/run/{id}", get(get_run))
+        .route("/api/run/{id}/cancel", post(cancel_run))
+        .route("/api/run/{id}/rerun-failed", post(rerun_failed))
         .route("/api/run/{id}/events", get(run_events))
+        .route("/api/run/{id}/artifacts/{*path}", get(get_artifact))
+        .route("/api/hooks/github", post(github_hook))
+        .route("/api/hooks/generic", post(generic_hook))
 }
 
 fn static_routes() -> Router<Arc<BossState>> {
@@ -421,27 +718,113 @@ fn static_routes() -> Router<Arc<BossState>> {
         .route("/{*path}", get(static_files))
 }
 
-pub async fn run(pipedef_path: &Path) {
-    let pipeline = parse_pipedef(pipedef_path);
-    println!("parsed pipedef: {} stages", pipeline.stages.len());
-    for stage in &pipeline.stages {
-        println!("  {} ({} tasks)", stage.name, stage.tasks.len());
+pub async fn run(pipedef_path: &Path, opts: BossOptions) {
+    // a broken pipedef doesn't stop the boss from coming up: the frontend shows
+    // what's wrong, and the watcher picks up the fix
+    let parsed = load_pipedef(pipedef_path);
+    if let Ok(pipedef) = &parsed {
+        println!("parsed pipedef: {} stages", pipedef.pipeline.stages.len());
+        for stage in &pipedef.pipeline.stages {
+            println!("  {} ({} tasks)", stage.name, stage.tasks.len());
+        }
     }
 
-    let lib_dir = setup_lib_dir();
+    let lib_dir = scheduler::setup_lib_dir();
     println!("bettertest lib dir: {}", lib_dir.display());
 
+    // default to .bettertest/runs/ next to the pipedef
+    let data_dir = opts.data_dir.unwrap_or_else(|| {
+        pipedef_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join("runs")
+    });
+    let store = RunStore::open(data_dir);
+    let last_run_id = store.max_run_id();
+    println!(
+        "run history: {} (last run #{last_run_id})",
+        store.dir().display()
+    );
+
+    let secrets = match &opts.secrets {
+        Some(path) => Secrets::load(path),
+        None => Secrets::default(),
+    };
+    let names: Vec<_> = secrets.names().collect();
+    if !names.is_empty() {
+        println!("secrets: {}", names.join(", "));
+    }
+
+    // a file rather than a flag so the secret doesn't show up in `ps`
+    let webhook_secret = opts.webhook_secret.as_ref().map(|path| {
+        std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read webhook secret {}: {e}", path.display()))
+            .trim()
+            .to_string()
+    });
+    if webhook_secret.is_some() {
+        println!("webhooks: /api/hooks/github, /api/hooks/generic");
+    }
+
+    let reporter = opts.status_url.map(|url| {
+        let token = opts.status_token.as_ref().map(|path| {
+            std::fs::read_to_string(path)
+                .unwrap_or_else(|e| panic!("failed to read status token {}: {e}", path.display()))
+                .trim()
+                .to_string()
+        });
+        println!("reporting commit statuses to {url}");
+        Arc::new(crate::status::ForgeReporter::new(url, token)) as Arc<dyn StatusReporter>
+    });
+
+    // task processes reach the pool's API over loopback, on the boss's own port
+    let pool = match &opts.workers {
+        Some(path) => {
+            let pool = Arc::new(WorkerPool::new(
+                pool::load(path),
+                "http://127.0.0.1:9001".into(),
+                opts.worker_token.clone(),
+            ));
+            let urls: Vec<_> = pool
+                .workers()
+                .iter()
+                .map(|w| w.url.as_str())
+                .collect();
+            println!("workers: {}", urls.join(", "));
+            pool.check_health().await;
+            pool.clone().spawn_health_checks();
+            Some(pool)
+        }
+        None => None,
+    };
+
     let state = Arc::new(BossState {
-        pipeline,
+        pipedef: std::sync::RwLock::new(None),
+        pipeline_error: std::sync::RwLock::new(None),
+        events: broadcast::channel(16).0,
         pipedef_path: pipedef_path.to_path_buf(),
         bettertest_lib_dir: lib_dir,
-        run_counter: AtomicU32::new(0),
-        active_run: Mutex::new(None),
+        run_counter: AtomicU32::new(last_run_id),
+        runs: Mutex::new(vec![]),
+        run_slots: Arc::new(Semaphore::new(opts.max_concurrent_runs.max(1))),
+        store,
+        secrets: Arc::new(secrets),
+        webhook_secret,
+        repos: opts.repos,
+        reporter,
+        public_url: opts.public_url,
+        pool: pool.clone(),
+        worker_token: opts.worker_token,
     });
 
-    let app = api_routes()
-        .merge(static_routes())
-        .with_state(state);
+    state.set_pipeline(parsed);
+    tokio::spawn(watch_pipedef(state.clone()));
+
+    let mut app = api_routes();
+    if let Some(pool) = pool {
+        app = app.merge(pool::routes(pool));
+    }
+    let app = app.merge(static_routes()).with_state(state);
 
     let socket = tokio::net::TcpSocket::new_v4().unwrap();
     socket.set_reuseaddr(true).unwrap();
@@ -452,3 +835,37 @@ pub async fn run(pipedef_path: &Path) {
     println!("bettertest boss running on http://localhost:9001");
     axum::serve(listener, app).await.unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn artifact_paths_stay_inside_the_run() {
+        assert_eq!(
+            artifact_path("Unit.task_a/htmlcov/index.html"),
+            Some(Path::new("Unit.task_a/htmlcov/index.html"))
+        );
+        assert_eq!(artifact_path("Unit.task_a/../../../etc/passwd"), None);
+        assert_eq!(artifact_path("/etc/passwd"), None);
+        assert_eq!(
+            artifact_path("Unit.task_a/./x"),
+            Some(Path::new("Unit.task_a/./x"))
+        );
+    }
+
+    #[test]
+    fn only_configured_repos_are_checked_out() {
+        let repos = vec!["https://github.com/me/project.git".to_string()];
+        for repo in [
+            "https://github.com/me/project.git",
+            "https://github.com/me/project",
+            "https://github.com/me/project/",
+        ] {
+            assert_eq!(allowed_repo(&repos, repo).as_ref(), Some(&repos[0]));
+        }
+        assert_eq!(allowed_repo(&repos, "https://github.com/me/other"), None);
+        assert_eq!(allowed_repo(&repos, "--upload-pack=touch /tmp/x"), None);
+        assert_eq!(allowed_repo(&[], "https://github.com/me/project"), None);
+    }
+}
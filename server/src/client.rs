@@ -0,0 +1,397 @@
+use crate::local::print_summary;
+use crate::scheduler::SseEvent;
+use crate::status::{StatusState, final_status};
+use bettertest_common::*;
+use clap::Subcommand;
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// exit code when the boss couldn't be asked, or said no
+const BOSS_ERROR: u8 = 2;
+/// exit code of `status` for a run that hasn't finished yet
+const STILL_RUNNING: u8 = 3;
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// start a run and print its id. of a git commit if --repo is given
+    Trigger {
+        #[arg(long)]
+        repo: Option<String>,
+        /// branch, tag or sha in --repo
+        #[arg(long = "ref", default_value = "HEAD")]
+        git_ref: String,
+        /// follow the run's output and exit with its result, like `logs`
+        #[arg(long)]
+        wait: bool,
+        /// file holding the boss's --webhook-secret, which git runs have to be signed with
+        #[arg(long)]
+        webhook_secret: Option<PathBuf>,
+    },
+    /// every task's state in a run (default: the latest run)
+    Status { run: Option<u32> },
+    /// stream a run's output until it's done (default: the latest run)
+    Logs {
+        run: Option<u32>,
+        /// only this task's output, unprefixed. `Stage.task`
+        #[arg(long)]
+        task: Option<String>,
+    },
+    /// cancel a queued or running run
+    Cancel { run: u32 },
+}
+
+struct Boss {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Boss {
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.url)
+    }
+
+    /// errors carry whatever the boss said about it
+    async fn check(resp: reqwest::Response) -> Result<reqwest::Response, String> {
+        let code = resp.status();
+        if code.is_success() {
+            return Ok(resp);
+        }
+        match resp.text().await.unwrap_or_default() {
+            body if body.is_empty() => Err(format!("boss returned {code}")),
+            body => Err(format!("boss returned {code}: {body}")),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let resp = self
+            .client
+            .get(self.url(path))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        Self::check(resp)
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn run_id(&self, run: Option<u32>) -> Result<u32, String> {
+        if let Some(run) = run {
+            return Ok(run);
+        }
+        let runs: Vec<RunSummary> = self.get("/api/runs").await?;
+        runs.first()
+            .map(|r| r.run_id)
+            .ok_or_else(|| "the boss has no runs yet".to_string())
+    }
+}
+
+/// `bettertest client`: drive a boss over its REST/SSE API. exits 0 if the
+/// run passed, 1 if it didn't, 2 if the boss couldn't be asked and 3 for
+/// `status` of a run that's still going
+pub async fn run(boss: &str, action: Action) -> ExitCode {
+    let boss = Boss {
+        client: reqwest::Client::new(),
+        url: boss.trim_end_matches('/').to_string(),
+    };
+    match act(&boss, action).await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(BOSS_ERROR)
+        }
+    }
+}
+
+async fn act(boss: &Boss, action: Action) -> Result<ExitCode, String> {
+    match action {
+        Action::Trigger {
+            repo,
+            git_ref,
+            wait,
+            webhook_secret,
+        } => {
+            let mut req = boss.client.post(boss.url("/api/run"));
+            if let Some(repo) = repo {
+                let body = serde_json::to_vec(&CreateRunRequest { repo, git_ref }).unwrap();
+                if let Some(path) = webhook_secret {
+                    let secret = std::fs::read_to_string(&path)
+                        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+                    req = req.header(
+                        crate::webhook::GENERIC_SIGNATURE,
+                        crate::webhook::sign(secret.trim(), &body),
+                    );
+                }
+                req = req
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body);
+            }
+            let resp = req.send().await.map_err(|e| e.to_string())?;
+            let created: RunCreated = Boss::check(resp)
+                .await?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            // bare, so scripts can `run=$(bettertest client trigger)`
+            println!("{}", created.run_id);
+            if !wait {
+                return Ok(ExitCode::SUCCESS);
+            }
+            follow(boss, created.run_id, None).await
+        }
+        Action::Status { run } => {
+            let run_id = boss.run_id(run).await?;
+            let run: PipelineRunState = boss.get(&format!("/api/run/{run_id}")).await?;
+            println!("run #{run_id}: {}", describe(&run));
+            print_summary(&run);
+            Ok(exit_code(&run))
+        }
+        Action::Logs { run, task } => {
+            let run_id = boss.run_id(run).await?;
+            let task = task
+                .map(|t| match t.split_once('.') {
+                    Some((stage, task)) => Ok(TaskId {
+                        stage: stage.into(),
+                        task: task.into(),
+                    }),
+                    None => Err(format!("--task takes Stage.task, not {t}")),
+                })
+                .transpose()?;
+            follow(boss, run_id, task).await
+        }
+        Action::Cancel { run } => {
+            let resp = boss
+                .client
+                .post(boss.url(&format!("/api/run/{run}/cancel")))
+                .send()
+                .await
+                .map_err(|e| e.to_string())?;
+            match resp.status() {
+                // the boss only knows live runs here, finished ones are a 404 or a 409
+                reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::CONFLICT => {
+                    Err(format!("run #{run} isn't queued or running"))
+                }
+                _ => {
+                    Boss::check(resp).await?;
+                    println!("cancelling run #{run}");
+                    Ok(ExitCode::SUCCESS)
+                }
+            }
+        }
+    }
+}
+
+fn describe(run: &PipelineRunState) -> &'static str {
+    if run.queued {
+        return "queued";
+    }
+    if run.active {
+        return "running";
+    }
+    match final_status(run).0 {
+        StatusState::Success => "passed",
+        StatusState::Failure => "failed",
+        StatusState::Pending | StatusState::Error => "cancelled",
+    }
+}
+
+fn exit_code(run: &PipelineRunState) -> ExitCode {
+    if run.active {
+        return ExitCode::from(STILL_RUNNING);
+    }
+    match final_status(run).0 {
+        StatusState::Success => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+/// print a run's output so far, then stream the rest until it's done.
+/// with `task`, only that task's output and without the `[Stage.task]` prefix
+async fn follow(boss: &Boss, run_id: u32, task: Option<TaskId>) -> Result<ExitCode, String> {
+    let run: PipelineRunState = boss.get(&format!("/api/run/{run_id}")).await?;
+    if let Some(id) = &task
+        && !run
+            .stages
+            .iter()
+            .any(|s| s.name == id.stage && s.tasks.iter().any(|t| t.name == id.task))
+    {
+        return Err(format!(
+            "run #{run_id} has no task {}.{}",
+            id.stage, id.task
+        ));
+    }
+
+    if !run.active {
+        print_output(&run, task.as_ref());
+        return Ok(finish(&run, task.as_ref()));
+    }
+    let resp = boss
+        .client
+        .get(boss.url(&format!("/api/run/{run_id}/events")))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    // finished in the meantime: only live runs have an event stream
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        let run: PipelineRunState = boss.get(&format!("/api/run/{run_id}")).await?;
+        print_output(&run, task.as_ref());
+        return Ok(finish(&run, task.as_ref()));
+    }
+    let mut resp = Boss::check(resp).await?;
+
+    let mut reader = SseReader::default();
+    let mut seen_state = false;
+    'stream: while let Some(chunk) = resp.chunk().await.map_err(|e| e.to_string())? {
+        for event in reader.feed(&chunk) {
+            let data: serde_json::Value = serde_json::from_str(&event.data).unwrap_or_default();
+            let wanted = |data: &serde_json::Value| match &task {
+                Some(id) => data["stage"] == id.stage.as_str() && data["task"] == id.task.as_str(),
+                None => true,
+            };
+            let prefix = format!(
+                "[{}.{}]",
+                data["stage"].as_str().unwrap_or_default(),
+                data["task"].as_str().unwrap_or_default()
+            );
+            match event.event.as_str() {
+                // the snapshot the stream opens with. later ones mean we fell behind
+                "state" if !seen_state => {
+                    seen_state = true;
+                    let Ok(run) = serde_json::from_value::<PipelineRunState>(data) else {
+                        continue;
+                    };
+                    print_output(&run, task.as_ref());
+                    // finished before we subscribed, there won't be a run_done
+                    if !run.active {
+                        break 'stream;
+                    }
+                }
+                "state" => eprintln!("(fell behind the boss, some output is missing)"),
+                "task_output" if wanted(&data) => {
+                    let line = data["line"].as_str().unwrap_or_default();
+                    match task {
+                        Some(_) => println!("{line}"),
+                        None => println!("{prefix} {line}"),
+                    }
+                }
+                "task_started" if task.is_none() => println!("{prefix} started"),
+                "task_result" if task.is_none() && data["state"] != "Skipped" => {
+                    println!("{prefix} {}", data["state"].as_str().unwrap_or_default())
+                }
+                "run_done" => break 'stream,
+                _ => {}
+            }
+        }
+    }
+
+    let run: PipelineRunState = boss.get(&format!("/api/run/{run_id}")).await?;
+    Ok(finish(&run, task.as_ref()))
+}
+
+/// exit code of a followed run: the task's result if one was picked
+fn finish(run: &PipelineRunState, task: Option<&TaskId>) -> ExitCode {
+    let Some(id) = task else {
+        print_summary(run);
+        return exit_code(run);
+    };
+    let state = run
+        .stages
+        .iter()
+        .filter(|s| s.name == id.stage)
+        .flat_map(|s| &s.tasks)
+        .find(|t| t.name == id.task)
+        .map(|t| &t.state);
+    match state {
+        Some(TaskState::Pass) => ExitCode::SUCCESS,
+        Some(TaskState::Pending | TaskState::Running) => ExitCode::from(STILL_RUNNING),
+        _ => ExitCode::FAILURE,
+    }
+}
+
+fn print_output(run: &PipelineRunState, task: Option<&TaskId>) {
+    for stage in &run.stages {
+        for t in &stage.tasks {
+            let lines = t.output.lines();
+            match task {
+                Some(id) if id.stage == stage.name && id.task == t.name => {
+                    lines.for_each(|l| println!("{l}"))
+                }
+                Some(_) => {}
+                None => lines.for_each(|l| println!("[{}.{}] {l}", stage.name, t.name)),
+            }
+        }
+    }
+}
+
+/// turns the bytes of a text/event-stream response back into events, however
+/// they happen to be split into chunks
+#[derive(Default)]
+struct SseReader {
+    buf: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl SseReader {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buf.extend_from_slice(bytes);
+        let mut events = vec![];
+        while let Some(end) = self.buf.iter().position(|b| *b == b'\n') {
+            let raw: Vec<u8> = self.buf.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&raw);
+            let line = line.trim_end_matches(['\n', '\r']);
+            let field = |name: &str| {
+                line.strip_prefix(name)
+                    .map(|v| v.strip_prefix(' ').unwrap_or(v).to_string())
+            };
+            if line.is_empty() {
+                // a blank line ends the event
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: std::mem::take(&mut self.event),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event.clear();
+                self.data.clear();
+            } else if let Some(event) = field("event:") {
+                self.event = event;
+            } else if let Some(data) = field("data:") {
+                self.data.push(data);
+            }
+            // comments (keep-alives) and ids are ignored
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_reader_handles_split_chunks() {
+        let mut reader = SseReader::default();
+        assert!(reader.feed(b"event: task_out").is_empty());
+        assert!(reader.feed(b"put\ndata: {\"line\":").is_empty());
+        let events = reader.feed(b"\"hi\"}\n\n:keep-alive\n\nevent: run_done\r\ndata: {}\r\n\r\n");
+        let events: Vec<_> = events
+            .iter()
+            .map(|e| (e.event.as_str(), e.data.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![("task_output", "{\"line\":\"hi\"}"), ("run_done", "{}")]
+        );
+    }
+
+    #[test]
+    fn sse_reader_joins_multiline_data() {
+        let mut reader = SseReader::default();
+        let events = reader.feed(b"event: state\ndata: one\ndata: two\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "one\ntwo");
+    }
+}
@@ -0,0 +1,40 @@
+use tokio::process::Command;
+
+pub(crate) fn is_sha(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// resolve a branch, tag or sha on `repo` to a full commit sha via `git ls-remote`.
+/// full shas are taken as-is since ls-remote only knows about refs
+pub(crate) async fn resolve(repo: &str, git_ref: &str) -> Result<String, String> {
+    if is_sha(git_ref) {
+        return Ok(git_ref.to_lowercase());
+    }
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--")
+        .arg(repo)
+        .arg(git_ref)
+        .arg(format!("{git_ref}^{{}}"))
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .await
+        .map_err(|e| format!("failed to run git: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string());
+    }
+    // `<sha>\t<refname>` per line. asking for `<tag>^{}` too gets an annotated
+    // tag's commit as well as the tag object, and the commit is the one we want
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let refs: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|l| l.split_once('\t'))
+        .collect();
+    refs.iter()
+        .find(|(_, name)| name.ends_with("^{}"))
+        .or(refs.first())
+        .map(|(sha, _)| sha.to_string())
+        .ok_or_else(|| format!("no ref named {git_ref} in {repo}"))
+}
@@ -0,0 +1,88 @@
+use bettertest_common::*;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+/// parse a JUnit XML report with python's xml.etree, like pipedefs get parsed
+/// with its ast module
+pub(crate) async fn parse(xml: &str) -> Result<Vec<TestCase>, String> {
+    let mut child = tokio::process::Command::new("python3")
+        .arg("-c")
+        .arg(include_str!("../scripts/parse_junit.py"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run python3: {e}"))?;
+    // the script reads all of stdin before writing anything, so this can't deadlock
+    let mut stdin = child.stdin.take().unwrap();
+    stdin
+        .write_all(xml.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    drop(stdin);
+
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last = stderr
+            .lines()
+            .rfind(|l| !l.trim().is_empty())
+            .unwrap_or("parse failed");
+        return Err(last.trim().to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn parses_pytest_report() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<testsuites><testsuite name="pytest" errors="1" failures="1" skipped="1" tests="4" time="0.52">
+  <testcase classname="test.test_models" name="test_create" time="0.012"/>
+  <testcase classname="test.test_models" name="test_delete" time="0.3">
+    <failure message="assert 1 == 2">def test_delete():
+&gt;       assert 1 == 2
+E       assert 1 == 2</failure>
+  </testcase>
+  <testcase classname="test.test_models" name="test_db" time="0.001">
+    <error message="fixture 'db' not found"/>
+  </testcase>
+  <testcase classname="test.test_models" name="test_later">
+    <skipped type="pytest.skip" message="not yet"/>
+  </testcase>
+</testsuite></testsuites>"#;
+        let cases = parse(xml).await.unwrap();
+        let summary: Vec<_> = cases
+            .iter()
+            .map(|c| (c.name.as_str(), c.status, c.duration_ms))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("test_create", TestStatus::Passed, Some(12)),
+                ("test_delete", TestStatus::Failed, Some(300)),
+                ("test_db", TestStatus::Error, Some(1)),
+                ("test_later", TestStatus::Skipped, None),
+            ]
+        );
+        assert_eq!(cases[0].class, "test.test_models");
+        assert_eq!(cases[0].message, None);
+        assert_eq!(
+            cases[1].message.as_deref(),
+            Some("assert 1 == 2\ndef test_delete():\n>       assert 1 == 2\nE       assert 1 == 2")
+        );
+        assert_eq!(cases[3].message.as_deref(), Some("not yet"));
+    }
+
+    #[tokio::test]
+    async fn reports_broken_xml() {
+        let err = parse("<testsuite><testcase").await.unwrap_err();
+        assert!(err.starts_with("xml.etree.ElementTree.ParseError"), "{err}");
+    }
+}
@@ -0,0 +1,179 @@
+use crate::pipedef;
+use crate::pool::{self, WorkerPool};
+use crate::scheduler::{self, ActiveRun, TaskCtx};
+use crate::secrets::Secrets;
+use bettertest_common::*;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+pub struct LocalOptions {
+    pub secrets: Option<PathBuf>,
+    pub artifacts: Option<PathBuf>,
+    pub workers: Option<PathBuf>,
+    pub worker_token: Option<String>,
+}
+
+/// `bettertest run`: one run of the pipedef right here, no boss or frontend.
+/// task output is printed as it arrives, prefixed with `[Stage.task]`
+pub async fn run(path: &Path, opts: LocalOptions) -> ExitCode {
+    let pipeline = match pipedef::parse(path) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let ctx = TaskCtx {
+        pipedef_path: path.to_path_buf(),
+        pipedef_dir: path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf(),
+        lib_dir: scheduler::setup_lib_dir(),
+        store: None,
+        secrets: Arc::new(
+            opts.secrets
+                .as_deref()
+                .map(Secrets::load)
+                .unwrap_or_default(),
+        ),
+        git: None,
+        artifacts: opts.artifacts,
+        worker_token: opts.worker_token.clone(),
+        pool: match &opts.workers {
+            Some(path) => Some(serve_pool(pool::load(path), opts.worker_token.clone()).await),
+            None => None,
+        },
+    };
+
+    let mut run_state = scheduler::initial_run_state(&pipeline, 1);
+    run_state.queued = false;
+    let active_run = Arc::new(ActiveRun::new(run_state));
+    let mut events = active_run.tx.subscribe();
+    let runner = tokio::spawn(scheduler::run_pipeline(
+        active_run.clone(),
+        pipeline,
+        vec![],
+        ctx,
+    ));
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            // same as cancelling on the boss: tasks get to clean up their containers
+            _ = tokio::signal::ctrl_c(), if !*active_run.cancel.borrow() => {
+                eprintln!("cancelling...");
+                active_run.cancel.send_replace(true);
+                continue;
+            }
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                eprintln!("({n} events dropped, output is incomplete)");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let data: serde_json::Value = serde_json::from_str(&event.data).unwrap_or_default();
+        let prefix = format!(
+            "[{}.{}]",
+            data["stage"].as_str().unwrap_or_default(),
+            data["task"].as_str().unwrap_or_default()
+        );
+        match event.event.as_str() {
+            "task_started" => println!("{prefix} started"),
+            "task_output" => println!("{prefix} {}", data["line"].as_str().unwrap_or_default()),
+            "task_result" if data["state"] != "Skipped" => {
+                println!("{prefix} {}", data["state"].as_str().unwrap_or_default())
+            }
+            "run_done" => break,
+            _ => {}
+        }
+    }
+    let _ = runner.await;
+
+    let run = active_run.snapshot().await;
+    print_summary(&run);
+    let failed = run
+        .stages
+        .iter()
+        .flat_map(|s| &s.tasks)
+        .any(|t| {
+            matches!(
+                t.state,
+                TaskState::Fail | TaskState::TimedOut | TaskState::Cancelled
+            )
+        });
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+pub(crate) fn print_summary(run: &PipelineRunState) {
+    let rows: Vec<_> = run
+        .stages
+        .iter()
+        .flat_map(|s| {
+            s.tasks.iter().map(|t| {
+                let time = t
+                    .result
+                    .duration_ms
+                    .map(|ms| format!("{:.1}s", ms as f64 / 1000.0))
+                    .unwrap_or_default();
+                (
+                    s.name.as_str(),
+                    t.name.as_str(),
+                    format!("{:?}", t.state),
+                    time,
+                )
+            })
+        })
+        .collect();
+    let stage_width = rows
+        .iter()
+        .map(|r| r.0.len())
+        .chain(["stage".len()])
+        .max()
+        .unwrap_or_default();
+    let task_width = rows
+        .iter()
+        .map(|r| r.1.len())
+        .chain(["task".len()])
+        .max()
+        .unwrap_or_default();
+    let state_width = rows
+        .iter()
+        .map(|r| r.2.len())
+        .chain(["result".len()])
+        .max()
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{:stage_width$}  {:task_width$}  {:state_width$}  time",
+        "stage", "task", "result"
+    );
+    for (stage, task, state, time) in &rows {
+        let line =
+            format!("{stage:stage_width$}  {task:task_width$}  {state:state_width$}  {time}");
+        println!("{}", line.trim_end());
+    }
+}
+
+/// the pool's API on a loopback port, since there's no boss to host it
+async fn serve_pool(workers: Vec<pool::Worker>, token: Option<String>) -> Arc<WorkerPool> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind the worker pool's port");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let pool = Arc::new(WorkerPool::new(workers, url, token));
+    pool.check_health().await;
+    pool.clone().spawn_health_checks();
+    tokio::spawn(axum::serve(listener, pool::routes(pool.clone())).into_future());
+    pool
+}
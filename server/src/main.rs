@@ -1,23 +1,135 @@
 mod boss;
+mod client;
+mod git;
+mod junit;
+mod local;
+mod pipedef;
+mod pool;
+mod scheduler;
+mod secrets;
+mod status;
+mod store;
+mod tar;
+mod validate;
+mod webhook;
 mod worker;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 #[derive(Parser)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(long)]
     worker: bool,
     #[arg(long)]
     boss: bool,
     #[arg(long)]
     pipedef: Option<PathBuf>,
+    /// where the boss keeps run history (default: `runs/` next to the pipedef)
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// `NAME=value` file of secrets pipedefs can read with `bettertest.secret()`.
+    /// every task can read all of them
+    #[arg(long)]
+    secrets: Option<PathBuf>,
+    /// how many runs the boss executes at once; the rest wait in a queue
+    #[arg(long, default_value_t = 1)]
+    max_runs: usize,
+    /// file holding the shared secret that signs webhook deliveries. enables /api/hooks/*
+    /// and git runs through POST /api/run
+    #[arg(long)]
+    webhook_secret: Option<PathBuf>,
+    /// repo runs may fetch and check out, from webhooks or POST /api/run. repeatable.
+    /// runs of any other repo are refused
+    #[arg(long = "repo")]
+    repos: Vec<String>,
+    /// github-style commit status endpoint for runs of a git commit, with `{sha}` in it,
+    /// e.g. https://api.github.com/repos/OWNER/REPO/statuses/{sha}
+    #[arg(long)]
+    status_url: Option<String>,
+    /// file holding the bearer token for --status-url
+    #[arg(long)]
+    status_token: Option<PathBuf>,
+    /// where the boss is reachable from outside, for links back to runs
+    #[arg(long, default_value = "http://localhost:9001")]
+    public_url: String,
+    /// how many containers the worker runs at once (default: no limit)
+    #[arg(long)]
+    max_containers: Option<usize>,
+    /// `key=value` label the worker advertises at /info, for tasks' `@requires`. repeatable
+    #[arg(long = "label", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+    /// file holding the bearer token workers require. the worker checks it, the boss sends
+    /// it. falls back to $BETTERTEST_WORKER_TOKEN
+    #[arg(long)]
+    worker_token: Option<PathBuf>,
+    /// file listing the workers `run(ANY, ...)` can go to: `URL [key=value ...]` per line
+    #[arg(long)]
+    workers: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// check a pipedef for mistakes without starting anything. exits non-zero if it finds any
+    Validate {
+        #[arg(long)]
+        pipedef: PathBuf,
+    },
+    /// run the whole pipedef once, here in the terminal. exits non-zero if any task fails
+    Run {
+        #[arg(long)]
+        pipedef: PathBuf,
+        /// `NAME=value` file of secrets, same as the boss's
+        #[arg(long)]
+        secrets: Option<PathBuf>,
+        /// keep the files tasks collect with `run(artifacts=...)` here
+        #[arg(long)]
+        artifacts: Option<PathBuf>,
+        /// same as the boss's
+        #[arg(long)]
+        workers: Option<PathBuf>,
+        /// same as the boss's
+        #[arg(long)]
+        worker_token: Option<PathBuf>,
+    },
+    /// talk to a running boss: start, watch and cancel runs
+    Client {
+        #[arg(long, default_value = "http://localhost:9001")]
+        boss: String,
+        #[command(subcommand)]
+        action: client::Action,
+    },
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Args::parse();
 
+    if let Some(command) = args.command {
+        return match command {
+            Command::Validate { pipedef } => validate::run(&pipedef),
+            Command::Run {
+                pipedef,
+                secrets,
+                artifacts,
+                workers,
+                worker_token,
+            } => {
+                let opts = local::LocalOptions {
+                    secrets,
+                    artifacts,
+                    workers,
+                    worker_token: worker::load_token(worker_token.as_deref()),
+                };
+                local::run(&pipedef, opts).await
+            }
+            Command::Client { boss, action } => client::run(&boss, action).await,
+        };
+    }
+
     match (args.boss, args.worker) {
         (true, true) => panic!("pick one bro: --boss or --worker, not both"),
         (false, false) => panic!("need either --boss or --worker"),
@@ -25,8 +137,35 @@ async fn main() {
             let pipedef = args
                 .pipedef
                 .expect("--boss requires --pipedef <path>");
-            boss::run(&pipedef).await
+            let opts = boss::BossOptions {
+                data_dir: args.data_dir,
+                secrets: args.secrets,
+                max_concurrent_runs: args.max_runs,
+                webhook_secret: args.webhook_secret,
+                repos: args.repos,
+                status_url: args.status_url,
+                status_token: args.status_token,
+                public_url: args.public_url,
+                workers: args.workers,
+                worker_token: worker::load_token(args.worker_token.as_deref()),
+            };
+            boss::run(&pipedef, opts).await
+        }
+        (false, true) => {
+            let opts = worker::WorkerOptions {
+                max_containers: args.max_containers,
+                labels: args.labels.into_iter().collect(),
+                token: worker::load_token(args.worker_token.as_deref()),
+            };
+            worker::run(opts).await
         }
-        (false, true) => worker::run().await,
     }
+    ExitCode::SUCCESS
+}
+
+fn parse_label(label: &str) -> Result<(String, String), String> {
+    let (key, value) = label
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got {label}"))?;
+    Ok((key.to_string(), value.to_string()))
 }
@@ -0,0 +1,340 @@
+use bettertest_common::*;
+use serde::Deserialize;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+
+#[derive(Debug)]
+pub(crate) enum PipedefError {
+    /// the pipedef file itself couldn't be read
+    Unreadable(std::io::Error),
+    /// python3 couldn't be started at all
+    NoPython(std::io::Error),
+    /// the parser script failed: a syntax error, a bad @needs, a missing file...
+    Python {
+        stderr: String,
+        /// the pipedef line python blamed, if it said
+        line: Option<u32>,
+    },
+    NotUtf8,
+    BadJson {
+        error: serde_json::Error,
+        output: String,
+    },
+}
+
+impl fmt::Display for PipedefError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipedefError::Unreadable(e) => write!(f, "failed to read pipedef: {e}"),
+            PipedefError::NoPython(e) => write!(f, "failed to run python3 - is it installed? {e}"),
+            PipedefError::Python { stderr, .. } => write!(f, "pipedef parse failed:\n{stderr}"),
+            PipedefError::NotUtf8 => write!(f, "python output wasn't utf8"),
+            PipedefError::BadJson { error, output } => {
+                write!(
+                    f,
+                    "failed to parse pipedef json: {error}\nraw output: {output}"
+                )
+            }
+        }
+    }
+}
+
+impl PipedefError {
+    /// one line for the banner, the whole story below it
+    pub(crate) fn to_dto(&self) -> PipedefErrorDto {
+        let (summary, line) = match self {
+            PipedefError::Python { stderr, line } => (
+                stderr
+                    .lines()
+                    .rfind(|l| !l.trim().is_empty())
+                    .unwrap_or("pipedef parse failed")
+                    .trim()
+                    .to_string(),
+                *line,
+            ),
+            PipedefError::Unreadable(e) => (format!("can't read the pipedef: {e}"), None),
+            PipedefError::NoPython(_) => ("python3 isn't installed".into(), None),
+            PipedefError::NotUtf8 | PipedefError::BadJson { .. } => {
+                ("the pipedef parser returned garbage".into(), None)
+            }
+        };
+        PipedefErrorDto {
+            summary,
+            line,
+            details: self.to_string(),
+        }
+    }
+}
+
+/// the last `File "<pipedef>", line N` of a traceback, or the parser's own `line N: ...`
+fn blamed_line(stderr: &str, path: &Path) -> Option<u32> {
+    let frame = format!("File \"{}\", line ", path.display());
+    stderr.lines().rev().find_map(|l| {
+        let digits = match l.trim().strip_prefix(&frame) {
+            Some(rest) => rest,
+            None => l.strip_prefix("line ")?,
+        };
+        digits
+            .split(|c: char| !c.is_ascii_digit())
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+/// run one of the embedded ast scripts over the pipedef's source, returning
+/// its stdout. passed with -c rather than through a temp file, so concurrent
+/// parses (and bosses) never see each other's half-written scripts. `path` is
+/// only for python's error messages
+fn run_script(script: &str, path: &Path, source: &str) -> Result<String, PipedefError> {
+    let mut child = std::process::Command::new("python3")
+        .arg("-c")
+        .arg(script)
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(PipedefError::NoPython)?;
+    // the scripts read all of stdin before writing anything, so this can't deadlock
+    let written = child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes());
+    let output = child
+        .wait_with_output()
+        .map_err(PipedefError::NoPython)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        let line = blamed_line(&stderr, path);
+        return Err(PipedefError::Python { stderr, line });
+    }
+    written.map_err(PipedefError::NoPython)?;
+
+    String::from_utf8(output.stdout).map_err(|_| PipedefError::NotUtf8)
+}
+
+pub(crate) fn parse(path: &Path) -> Result<PipelineDto, PipedefError> {
+    parse_source(path, &read(path)?)
+}
+
+pub(crate) fn read(path: &Path) -> Result<String, PipedefError> {
+    std::fs::read_to_string(path).map_err(PipedefError::Unreadable)
+}
+
+/// parse a pipedef that's already been read, so the caller knows exactly
+/// which text the pipeline came from
+pub(crate) fn parse_source(path: &Path, source: &str) -> Result<PipelineDto, PipedefError> {
+    let script = include_str!("../scripts/parse_pipedef.py");
+    let stdout = run_script(script, path, source)?;
+    serde_json::from_str(&stdout).map_err(|error| PipedefError::BadJson {
+        error,
+        output: stdout,
+    })
+}
+
+/// something in a pipedef that parses but won't do what it looks like
+#[derive(Deserialize, Debug, PartialEq)]
+pub(crate) struct Problem {
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+/// lint a pipedef that already parses: see validate_pipedef.py
+pub(crate) fn problems(path: &Path) -> Result<Vec<Problem>, PipedefError> {
+    let script = include_str!("../scripts/validate_pipedef.py");
+    let stdout = run_script(script, path, &read(path)?)?;
+    stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Problem>, _>>()
+        .map_err(|error| PipedefError::BadJson {
+            error,
+            output: stdout.clone(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blamed_line_from_syntax_error() {
+        let stderr = r#"Traceback (most recent call last):
+  File "<string>", line 41, in <module>
+    tree = ast.parse(open(sys.argv[1]).read(), sys.argv[1])
+  File "/usr/lib/python3.11/ast.py", line 50, in parse
+    return compile(source, filename, mode, flags,
+  File "/srv/repo/.bettertest/pipedef.py", line 33
+    class Broken(
+                ^
+SyntaxError: '(' was never closed
+"#;
+        let path = Path::new("/srv/repo/.bettertest/pipedef.py");
+        assert_eq!(blamed_line(stderr, path), Some(33));
+    }
+
+    #[test]
+    fn blamed_line_from_parser_message() {
+        let stderr = "line 12: @needs takes stage classes or task methods\n";
+        assert_eq!(blamed_line(stderr, Path::new("pipedef.py")), Some(12));
+    }
+
+    #[test]
+    fn needs_have_to_exist_and_not_loop() {
+        let path = std::env::temp_dir().join("pipedef.py");
+        let error = |source: &str| match parse_source(&path, source) {
+            Err(PipedefError::Python { stderr, line }) => (line, stderr.trim().to_string()),
+            other => panic!("parsed: {:?}", other.map(|_| ())),
+        };
+        assert_eq!(
+            error("class Unit(Stage):\n    @needs(Unit.task_gone)\n    def task_a(): pass\n"),
+            (
+                Some(2),
+                "line 2: @needs(Unit.task_gone): there's no such task".into()
+            )
+        );
+        assert_eq!(
+            error(
+                "class Build(Stage):\n    @needs(Test.task_x)\n    def task_a(): pass\n\n\
+                 class Test(Stage):\n    def task_x(): pass\n"
+            ),
+            (
+                Some(2),
+                "line 2: @needs makes a cycle, Build.task_a -> Test.task_x -> Build.task_a".into()
+            )
+        );
+        // a stage needing itself has every task wait on every other one
+        assert_eq!(
+            error(
+                "@needs(Unit)\nclass Unit(Stage):\n    def task_a(): pass\n    def task_b(): pass\n"
+            ),
+            (
+                Some(1),
+                "line 1: @needs makes a cycle, Unit.task_a -> Unit.task_b -> Unit.task_a".into()
+            )
+        );
+    }
+
+    #[test]
+    fn requires_labels_merge_stage_and_task() {
+        let path =
+            std::env::temp_dir().join(format!("bettertest_requires_{}.py", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+from bettertest import Stage, requires
+
+@requires(mem="high")
+class Big(Stage):
+    def task_a(): pass
+
+    @requires(gpu=True, mem="huge")
+    def task_b(): pass
+
+class Small(Stage):
+    def task_c(): pass
+"#,
+        )
+        .unwrap();
+        let parsed = parse(&path);
+        std::fs::remove_file(&path).unwrap();
+        let pipeline = parsed.unwrap();
+        let labels = |stage: usize, task: &str| {
+            pipeline.stages[stage]
+                .labels
+                .get(task)
+                .map(|l| {
+                    l.iter()
+                        .map(|(k, v)| format!("{k}={v}"))
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(labels(0, "task_a"), Some(vec!["mem=high".to_string()]));
+        assert_eq!(
+            labels(0, "task_b"),
+            Some(vec!["gpu=true".to_string(), "mem=huge".to_string()])
+        );
+        assert_eq!(labels(1, "task_c"), None);
+    }
+
+    #[test]
+    fn validate_flags_pipedef_mistakes() {
+        let path =
+            std::env::temp_dir().join(format!("bettertest_validate_{}.py", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+from bettertest import Stage
+
+class Empty(Stage):
+    pass
+
+class NoTasks(Stage):
+    def helper(): pass
+
+class Unit(Stage):
+    def task_a(): pass
+
+    def task_a(): pass
+
+    @classmethod
+    def task_b(cls): pass
+
+    def task_c(self): pass
+
+class More(Unit):
+    def task_d(): pass
+
+class Integration(Unit, Stage):
+    def task_a(): pass
+
+class NoTasks(Stage):
+    def task_e(): pass
+"#,
+        )
+        .unwrap();
+        let found = parse(&path).and_then(|_| problems(&path));
+        std::fs::remove_file(&path).unwrap();
+        let found: Vec<_> = found
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.line, p.message))
+            .collect();
+        let expected = [
+            (4, "stage Empty is empty"),
+            (
+                7,
+                "stage NoTasks has no task_ methods, so it never runs anything",
+            ),
+            (
+                13,
+                "Unit.task_a is defined twice (first on line 11), only the last one runs",
+            ),
+            (16, "Unit.task_b is a classmethod, tasks must be static"),
+            (
+                18,
+                "Unit.task_c takes arguments, but tasks are called with none (drop `self`)",
+            ),
+            (
+                20,
+                "More subclasses a stage but not Stage itself, so it never runs \
+                 (use `class More(Unit, Stage)`)",
+            ),
+            (24, "Integration.task_a shadows Unit.task_a"),
+            (26, "stage NoTasks is defined twice, only the last one runs"),
+        ]
+        .map(|(line, message)| (Some(line), message.to_string()));
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn blamed_line_missing() {
+        let stderr = "FileNotFoundError: [Errno 2] No such file or directory: 'pipedef.py'\n";
+        assert_eq!(blamed_line(stderr, Path::new("pipedef.py")), None);
+    }
+}
@@ -0,0 +1,508 @@
+//! the workers a boss (or `bettertest run`) hands out to tasks that ask for
+//! `run(ANY, ...)` or a label instead of a worker url
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    middleware,
+    routing::{delete, get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// how often every worker's /health is polled
+const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(crate) struct Worker {
+    pub url: String,
+    /// from the workers file. these win over the ones the worker advertises
+    pub labels: BTreeMap<String, String>,
+}
+
+/// read a workers file: one `URL [key=value ...]` per line, `#` comments
+pub(crate) fn load(path: &std::path::Path) -> Vec<Worker> {
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("failed to read workers file {}: {e}", path.display()));
+    parse(&text).unwrap_or_else(|(line, e)| panic!("{}:{line}: {e}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Vec<Worker>, (usize, String)> {
+    let mut workers = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let mut words = line
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        let Some(url) = words.next() else {
+            continue;
+        };
+        let mut labels = BTreeMap::new();
+        for word in words {
+            let Some((key, value)) = word.split_once('=') else {
+                return Err((i + 1, format!("expected key=value, got {word}")));
+            };
+            labels.insert(key.to_string(), value.to_string());
+        }
+        workers.push(Worker {
+            url: url.trim_end_matches('/').to_string(),
+            labels,
+        });
+    }
+    Ok(workers)
+}
+
+pub(crate) struct WorkerPool {
+    /// where task processes reach the pool's API, handed to them as BETTERTEST_POOL
+    pub url: String,
+    workers: Vec<Worker>,
+    state: Mutex<PoolState>,
+    client: reqwest::Client,
+    /// for the workers' /info
+    token: Option<String>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    /// by index into `workers`. None until the first check
+    healthy: Vec<Option<bool>>,
+    /// what each worker's `GET /info` said about itself, last we heard. None
+    /// until it's been reachable
+    advertised: Vec<Option<BTreeMap<String, String>>>,
+    leases: HashMap<u64, Lease>,
+    next_lease: u64,
+}
+
+impl PoolState {
+    /// leases currently out on a worker
+    fn load(&self, worker: usize) -> usize {
+        self.leases
+            .values()
+            .filter(|l| l.worker == worker)
+            .count()
+    }
+}
+
+struct Lease {
+    worker: usize,
+    /// BETTERTEST_TASK of the task holding it, so its leases can be dropped
+    /// when the task's process exits without giving them back
+    task: String,
+}
+
+/// a worker's `GET /info`
+#[derive(Deserialize)]
+struct Info {
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct LeaseRequest {
+    #[serde(default)]
+    task: String,
+    /// only workers with all of these
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct LeaseResponse {
+    id: u64,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct WorkerStatus {
+    url: String,
+    labels: BTreeMap<String, String>,
+    healthy: bool,
+    /// tasks running on it right now
+    leases: usize,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(workers: Vec<Worker>, url: String, token: Option<String>) -> Self {
+        let state = PoolState {
+            healthy: vec![None; workers.len()],
+            advertised: vec![None; workers.len()],
+            ..Default::default()
+        };
+        WorkerPool {
+            url,
+            workers,
+            state: Mutex::new(state),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(3))
+                .build()
+                .unwrap(),
+            token,
+        }
+    }
+
+    pub(crate) fn workers(&self) -> &[Worker] {
+        &self.workers
+    }
+
+    /// poll every worker's /health once, logging the ones that went up or
+    /// down, and pick up the labels the healthy ones advertise
+    pub(crate) async fn check_health(&self) {
+        let checks = self.workers.iter().map(|w| async {
+            let healthy = self
+                .client
+                .get(format!("{}/health", w.url))
+                .send()
+                .await
+                .is_ok_and(|r| r.status().is_success());
+            if !healthy {
+                return (false, None);
+            }
+            let mut request = self.client.get(format!("{}/info", w.url));
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let info = match request.send().await {
+                Ok(resp) if resp.status() == reqwest::StatusCode::UNAUTHORIZED => {
+                    match &self.token {
+                        Some(_) => eprintln!("worker {} rejected the worker token", w.url),
+                        None => eprintln!("worker {} wants a token, see --worker-token", w.url),
+                    }
+                    None
+                }
+                Ok(resp) if resp.status().is_success() => resp.json::<Info>().await.ok(),
+                // a worker from before /info has nothing to advertise
+                Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => Some(Info {
+                    labels: BTreeMap::new(),
+                }),
+                _ => None,
+            };
+            (true, info)
+        });
+        let results = futures::future::join_all(checks).await;
+        let mut state = self.state.lock().unwrap();
+        for (i, (healthy, info)) in results.into_iter().enumerate() {
+            if state.healthy[i] != Some(healthy) {
+                let status = if healthy { "up" } else { "down" };
+                println!("worker {} is {status}", self.workers[i].url);
+            }
+            state.healthy[i] = Some(healthy);
+            if let Some(info) = info {
+                state.advertised[i] = Some(info.labels);
+            }
+        }
+    }
+
+    /// a worker's advertised labels with the workers file's on top
+    fn labels(&self, state: &PoolState, worker: usize) -> BTreeMap<String, String> {
+        let mut labels = state.advertised[worker]
+            .clone()
+            .unwrap_or_default();
+        labels.extend(self.workers[worker].labels.clone());
+        labels
+    }
+
+    /// whether a worker has all of `labels`, up or not. None if that depends
+    /// on labels it hasn't advertised yet
+    fn matches(
+        &self,
+        state: &PoolState,
+        worker: usize,
+        labels: &BTreeMap<String, String>,
+    ) -> Option<bool> {
+        let has = self.labels(state, worker);
+        if labels
+            .iter()
+            .all(|(k, v)| has.get(k) == Some(v))
+        {
+            return Some(true);
+        }
+        let contradicts = labels.iter().any(|(k, v)| {
+            self.workers[worker]
+                .labels
+                .get(k)
+                .is_some_and(|h| h != v)
+        });
+        if state.advertised[worker].is_none() && !contradicts {
+            return None;
+        }
+        Some(false)
+    }
+
+    /// whether a task that requires `labels` has anywhere to go at all. only
+    /// an error once every worker that could have them has said it doesn't
+    pub(crate) fn check_labels(&self, labels: &BTreeMap<String, String>) -> Result<(), String> {
+        let state = self.state.lock().unwrap();
+        if (0..self.workers.len()).all(|i| self.matches(&state, i, labels) == Some(false)) {
+            return Err(no_match(labels));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn spawn_health_checks(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(HEALTH_INTERVAL).await;
+                self.check_health().await;
+            }
+        });
+    }
+
+    /// the healthy worker with the fewest leases among those with all of
+    /// `labels`. 503 while none of them is up (or has said what it has), 404
+    /// if none ever will be
+    fn lease(
+        &self,
+        task: &str,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<LeaseResponse, (StatusCode, String)> {
+        let mut state = self.state.lock().unwrap();
+        let candidates: Vec<_> = (0..self.workers.len())
+            .map(|i| (i, self.matches(&state, i, labels)))
+            .filter(|(_, m)| *m != Some(false))
+            .collect();
+        if candidates.is_empty() {
+            return Err((StatusCode::NOT_FOUND, no_match(labels)));
+        }
+        let Some(worker) = candidates
+            .iter()
+            .filter(|(i, m)| *m == Some(true) && state.healthy[*i] == Some(true))
+            .map(|(i, _)| *i)
+            .min_by_key(|&i| state.load(i))
+        else {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("no healthy worker: wanted {}", wanted(labels)),
+            ));
+        };
+        state.next_lease += 1;
+        let id = state.next_lease;
+        state.leases.insert(
+            id,
+            Lease {
+                worker,
+                task: task.to_string(),
+            },
+        );
+        Ok(LeaseResponse {
+            id,
+            url: self.workers[worker].url.clone(),
+        })
+    }
+
+    fn release(&self, id: u64) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .leases
+            .remove(&id)
+            .is_some()
+    }
+
+    /// drop whatever a task still holds, e.g. after it was killed mid-`run()`
+    pub(crate) fn release_task(&self, task: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .leases
+            .retain(|_, l| l.task != task);
+    }
+}
+
+fn no_match(labels: &BTreeMap<String, String>) -> String {
+    format!("no worker in the pool matches: wanted {}", wanted(labels))
+}
+
+/// "a worker with gpu=true,mem=high", for errors
+fn wanted(labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return "any worker".into();
+    }
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    format!("a worker with {}", labels.join(","))
+}
+
+async fn list_workers(State(pool): State<Arc<WorkerPool>>) -> Json<Vec<WorkerStatus>> {
+    let state = pool.state.lock().unwrap();
+    let statuses: Vec<_> = pool
+        .workers
+        .iter()
+        .enumerate()
+        .map(|(i, w)| WorkerStatus {
+            url: w.url.clone(),
+            labels: pool.labels(&state, i),
+            healthy: state.healthy[i] == Some(true),
+            leases: state.load(i),
+        })
+        .collect();
+    Json(statuses)
+}
+
+async fn create_lease(
+    State(pool): State<Arc<WorkerPool>>,
+    Json(req): Json<LeaseRequest>,
+) -> Result<Json<LeaseResponse>, (StatusCode, String)> {
+    pool.lease(&req.task, &req.labels).map(Json)
+}
+
+async fn delete_lease(State(pool): State<Arc<WorkerPool>>, Path(id): Path<u64>) -> StatusCode {
+    if pool.release(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// behind the worker token when there is one, same as the workers: a lease
+/// is a worker handed out, and the list tells what's out there
+pub(crate) fn routes<S>(pool: Arc<WorkerPool>) -> Router<S> {
+    let mut router = Router::new()
+        .route("/api/workers", get(list_workers))
+        .route("/api/workers/lease", post(create_lease))
+        .route("/api/workers/lease/{id}", delete(delete_lease));
+    if let Some(token) = &pool.token {
+        let check =
+            middleware::from_fn_with_state(Arc::new(token.clone()), crate::worker::require_token);
+        router = router.route_layer(check);
+    }
+    router.with_state(pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(text: &str, healthy: &[bool]) -> WorkerPool {
+        let pool = WorkerPool::new(parse(text).unwrap(), String::new(), None);
+        pool.state.lock().unwrap().healthy = healthy.iter().map(|h| Some(*h)).collect();
+        pool
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_workers_file() {
+        let workers = parse(
+            "# the test boxes\n\
+             http://box1:9009/ gpu=false\n\
+             \n\
+             http://box2:9009 gpu=true mem=high  # the big one\n",
+        )
+        .unwrap();
+        let parsed: Vec<_> = workers
+            .iter()
+            .map(|w| (w.url.as_str(), w.labels.len()))
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![("http://box1:9009", 1), ("http://box2:9009", 2)]
+        );
+        assert_eq!(workers[1].labels["mem"], "high");
+        assert_eq!(
+            parse("http://box1:9009\nhttp://box2:9009 gpu").err(),
+            Some((2, "expected key=value, got gpu".to_string()))
+        );
+    }
+
+    #[test]
+    fn leases_go_to_the_least_loaded_healthy_worker() {
+        let pool = pool("http://a\nhttp://b\nhttp://c", &[true, true, false]);
+        let any = BTreeMap::new();
+        let urls: Vec<_> = (0..4)
+            .map(|_| pool.lease("1/Unit.task_a", &any).unwrap().url)
+            .collect();
+        assert_eq!(urls, vec!["http://a", "http://b", "http://a", "http://b"]);
+
+        assert!(pool.release(1));
+        assert!(!pool.release(1));
+        assert_eq!(pool.lease("1/Unit.task_b", &any).unwrap().url, "http://a");
+
+        pool.release_task("1/Unit.task_a");
+        assert_eq!(pool.state.lock().unwrap().leases.len(), 1);
+    }
+
+    #[test]
+    fn leases_respect_labels() {
+        let pool = pool("http://a gpu=false\nhttp://b gpu=true", &[true, false]);
+        assert_eq!(
+            pool.lease("t", &labels(&[("gpu", "false")]))
+                .unwrap()
+                .url,
+            "http://a"
+        );
+        assert_eq!(
+            pool.lease("t", &labels(&[("gpu", "true")])),
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no healthy worker: wanted a worker with gpu=true".to_string()
+            ))
+        );
+        // b hasn't said what else it has
+        assert_eq!(
+            pool.lease("t", &labels(&[("mem", "high")])),
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no healthy worker: wanted a worker with mem=high".to_string()
+            ))
+        );
+        pool.state.lock().unwrap().advertised = vec![Some(BTreeMap::new()); 2];
+        assert_eq!(
+            pool.lease("t", &labels(&[("mem", "high")])),
+            Err((
+                StatusCode::NOT_FOUND,
+                "no worker in the pool matches: wanted a worker with mem=high".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn advertised_labels_count_unless_the_file_says_otherwise() {
+        let pool = pool("http://a\nhttp://b gpu=false", &[true, true]);
+        {
+            let mut state = pool.state.lock().unwrap();
+            state.advertised[0] = Some(labels(&[("mem", "high")]));
+            state.advertised[1] = Some(labels(&[("mem", "high"), ("gpu", "true")]));
+        }
+        assert_eq!(pool.check_labels(&labels(&[("mem", "high")])), Ok(()));
+        assert_eq!(
+            pool.lease("t", &labels(&[("gpu", "true")])),
+            Err((
+                StatusCode::NOT_FOUND,
+                "no worker in the pool matches: wanted a worker with gpu=true".to_string()
+            ))
+        );
+        assert_eq!(
+            pool.lease("t", &labels(&[("gpu", "false"), ("mem", "high")]))
+                .unwrap()
+                .url,
+            "http://b"
+        );
+    }
+
+    #[test]
+    fn unknown_labels_are_not_a_mismatch() {
+        let pool = pool("http://a gpu=false\nhttp://b gpu=false", &[true, false]);
+        // neither has advertised anything yet, so either might have mem=high
+        assert_eq!(pool.check_labels(&labels(&[("mem", "high")])), Ok(()));
+        // but the workers file already rules out gpu=true
+        assert_eq!(
+            pool.check_labels(&labels(&[("gpu", "true")])),
+            Err("no worker in the pool matches: wanted a worker with gpu=true".to_string())
+        );
+        pool.state.lock().unwrap().advertised = vec![Some(labels(&[("mem", "low")])); 2];
+        assert_eq!(
+            pool.check_labels(&labels(&[("mem", "high")])),
+            Err("no worker in the pool matches: wanted a worker with mem=high".to_string())
+        );
+    }
+}
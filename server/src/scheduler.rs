@@ -0,0 +1,865 @@
+//! the stage/task loop behind every run, shared by the boss and `bettertest run`
+
+use crate::pool::WorkerPool;
+use crate::secrets::Secrets;
+use crate::store::{RunStore, now_secs};
+use bettertest_common::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncBufReadExt;
+use tokio::sync::{Mutex, broadcast, watch};
+
+/// how long a cancelled task gets to clean up its container before SIGKILL.
+/// run_task.py exits on its own as soon as its stdin closes
+const CANCEL_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub(crate) struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+pub(crate) struct ActiveRun {
+    pub run_id: u32,
+    pub state: Mutex<PipelineRunState>,
+    /// everything that happens to the run, as the SSE events the frontend gets
+    pub tx: broadcast::Sender<SseEvent>,
+    pub active: AtomicBool,
+    pub cancel: watch::Sender<bool>,
+    /// held while the run is written to the store
+    saving: Mutex<()>,
+}
+
+impl ActiveRun {
+    pub(crate) fn new(state: PipelineRunState) -> Self {
+        let (tx, _) = broadcast::channel(256);
+        ActiveRun {
+            run_id: state.run_id,
+            state: Mutex::new(state),
+            tx,
+            active: AtomicBool::new(true),
+            cancel: watch::Sender::new(false),
+            saving: Mutex::new(()),
+        }
+    }
+
+    pub(crate) async fn snapshot(&self) -> PipelineRunState {
+        let mut snapshot = self.state.lock().await.clone();
+        snapshot.active = self.active.load(Ordering::Relaxed);
+        snapshot
+    }
+}
+
+#[derive(Serialize)]
+struct StageEvent<'a> {
+    stage: &'a str,
+}
+
+#[derive(Serialize)]
+struct TaskEvent<'a> {
+    stage: &'a str,
+    task: &'a str,
+}
+
+#[derive(Serialize)]
+struct TaskOutputEvent<'a> {
+    stage: &'a str,
+    task: &'a str,
+    line: &'a str,
+}
+
+pub(crate) fn initial_run_state(pipeline: &PipelineDto, run_id: u32) -> PipelineRunState {
+    PipelineRunState {
+        run_id,
+        active: true,
+        started_at: Some(now_secs()),
+        finished_at: None,
+        parent_run_id: None,
+        queued: true,
+        git: None,
+        stages: pipeline
+            .stages
+            .iter()
+            .map(|s| StageRunState {
+                name: s.name.clone(),
+                tasks: s
+                    .tasks
+                    .iter()
+                    .map(|t| TaskRunState {
+                        name: t.clone(),
+                        state: TaskState::Pending,
+                        output: String::new(),
+                        result: TaskResult::default(),
+                    })
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn setup_lib_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join("bettertest_lib");
+    std::fs::create_dir_all(&dir).expect("failed to create bettertest lib dir");
+    std::fs::write(
+        dir.join("bettertest.py"),
+        include_str!("../../bettertest/__init__.py"),
+    )
+    .expect("failed to write bettertest.py");
+    std::fs::write(
+        dir.join("run_task.py"),
+        include_str!("../scripts/run_task.py"),
+    )
+    .expect("failed to write run_task.py");
+    dir
+}
+
+/// parallel tasks finishing together each save the run. they take turns, so
+/// the file always ends up with the newest snapshot
+pub(crate) async fn persist(active_run: &ActiveRun, store: &RunStore) {
+    let _saving = active_run.saving.lock().await;
+    let snapshot = active_run.snapshot().await;
+    let store = store.clone();
+    let saved = tokio::task::spawn_blocking(move || store.save(&snapshot)).await;
+    if let Ok(Err(e)) = saved {
+        eprintln!("failed to save run {}: {e}", active_run.run_id);
+    }
+}
+
+/// everything a task needs besides the run it belongs to
+#[derive(Clone)]
+pub(crate) struct TaskCtx {
+    pub pipedef_path: PathBuf,
+    /// where the pipedef's own imports are found. the boss runs tasks against a
+    /// copy of the pipedef that lives somewhere else
+    pub pipedef_dir: PathBuf,
+    pub lib_dir: PathBuf,
+    /// where the run is saved after every task. None keeps it in memory only
+    pub store: Option<RunStore>,
+    pub secrets: Arc<Secrets>,
+    pub git: Option<GitCheckout>,
+    /// the run's artifacts go in `{stage}.{task}/` under this. None means
+    /// `run(artifacts=...)` collects nothing
+    pub artifacts: Option<PathBuf>,
+    /// workers for `run(ANY, ...)` and label selectors
+    pub pool: Option<Arc<WorkerPool>>,
+    /// sent along with every request bettertest.run() makes to a worker
+    pub worker_token: Option<String>,
+}
+
+impl TaskCtx {
+    async fn save(&self, active_run: &ActiveRun) {
+        if let Some(store) = &self.store {
+            persist(active_run, store).await;
+        }
+    }
+}
+
+/// run one task through run_task.py, streaming its output into the run
+async fn run_task(
+    active_run: Arc<ActiveRun>,
+    ctx: TaskCtx,
+    stage_name: String,
+    task_name: String,
+    labels: BTreeMap<String, String>,
+) -> TaskState {
+    // bettertest.run() leaves a note here: which worker it used, how its
+    // container exited or whether it timed out, and any JUnit reports
+    let result_path = std::env::temp_dir().join(format!(
+        "bettertest_result_{}_{}_{stage_name}.{task_name}.json",
+        std::process::id(),
+        active_run.run_id
+    ));
+    let _ = std::fs::remove_file(&result_path);
+
+    let mut command = tokio::process::Command::new("python3");
+    command
+        .arg("-u")
+        .arg(ctx.lib_dir.join("run_task.py"))
+        .arg(&ctx.lib_dir)
+        .arg(&ctx.pipedef_path)
+        .arg(&ctx.pipedef_dir)
+        .arg(&stage_name)
+        .arg(&task_name)
+        .env("BETTERTEST_SECRETS", ctx.secrets.to_json())
+        .env("BETTERTEST_RESULT", &result_path)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+    // picked up by bettertest.run(), which asks the worker to check this commit out
+    if let Some(git) = &ctx.git {
+        command
+            .env("BETTERTEST_REPO", &git.repo)
+            .env("BETTERTEST_SHA", &git.sha);
+    }
+    // bettertest.run() writes the files it gets back from the worker in here
+    let artifacts_dir = ctx
+        .artifacts
+        .as_ref()
+        .map(|dir| dir.join(format!("{stage_name}.{task_name}")));
+    if let Some(dir) = &artifacts_dir {
+        command.env("BETTERTEST_ARTIFACTS", dir);
+    }
+    // bettertest.run() leases workers from the pool in the task's name
+    let task_key = format!("{}/{stage_name}.{task_name}", active_run.run_id);
+    if let Some(pool) = &ctx.pool {
+        command
+            .env("BETTERTEST_POOL", &pool.url)
+            .env("BETTERTEST_TASK", &task_key);
+    }
+    if let Some(token) = &ctx.worker_token {
+        command.env(crate::worker::TOKEN_ENV, token);
+    }
+    // its @requires, which run(ANY, ...) asks the pool for
+    if !labels.is_empty() {
+        command.env("BETTERTEST_LABELS", serde_json::to_string(&labels).unwrap());
+    }
+    let spawn_result = command.spawn().map_err(|e| e.to_string());
+    let started = std::time::Instant::now();
+    let started_at = now_secs();
+    {
+        let mut st = active_run.state.lock().await;
+        if let Some(t) = find_task(&mut st, &stage_name, &task_name) {
+            t.result.started_at = Some(started_at);
+        }
+    }
+
+    // the task may only name worker urls, so this is a heads-up rather than a failure
+    if let Some(e) = ctx
+        .pool
+        .as_ref()
+        .and_then(|p| p.check_labels(&labels).err())
+    {
+        let line = format!("warning: {e}, so this task's run(ANY, ...) calls will fail");
+        push_output(&active_run, &stage_name, &task_name, &line).await;
+    }
+
+    let mut cancel_rx = active_run.cancel.subscribe();
+    let mut terminating = false;
+    let mut exit_code = None;
+    let passed = match spawn_result {
+        Ok(mut child) => {
+            // closing it is how a task gets told to stop. unlike signalling its
+            // pid, that can't hit some other process once the task is gone
+            let mut stdin = child.stdin.take();
+            let (line_tx, mut line_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+
+            let stdout = child.stdout.take().unwrap();
+            let tx = line_tx.clone();
+            tokio::spawn(async move {
+                let mut lines = tokio::io::BufReader::new(stdout).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let _ = tx.send(line);
+                }
+            });
+
+            let stderr = child.stderr.take().unwrap();
+            tokio::spawn(async move {
+                let mut lines = tokio::io::BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let _ = line_tx.send(line);
+                }
+            });
+
+            let mut kill_at = None;
+            loop {
+                let deadline = kill_at.unwrap_or_else(tokio::time::Instant::now);
+                let line = tokio::select! {
+                    line = line_rx.recv() => match line {
+                        Some(line) => ctx.secrets.mask(&line),
+                        None => break,
+                    },
+                    _ = cancel_rx.wait_for(|c| *c), if !terminating => {
+                        drop(stdin.take());
+                        terminating = true;
+                        kill_at = Some(tokio::time::Instant::now() + CANCEL_GRACE);
+                        continue;
+                    }
+                    _ = tokio::time::sleep_until(deadline), if kill_at.is_some() => {
+                        child.start_kill().ok();
+                        kill_at = None;
+                        continue;
+                    }
+                };
+                push_output(&active_run, &stage_name, &task_name, &line).await;
+            }
+
+            let status = child.wait().await;
+            exit_code = status.as_ref().ok().and_then(|s| s.code());
+            status.is_ok_and(|s| s.success())
+        }
+        Err(e) => {
+            push_output(&active_run, &stage_name, &task_name, &e).await;
+            false
+        }
+    };
+
+    if let Some(pool) = &ctx.pool {
+        pool.release_task(&task_key);
+    }
+
+    let note = read_note(&result_path);
+    let task_state = if terminating {
+        TaskState::Cancelled
+    } else if note.timed_out {
+        TaskState::TimedOut
+    } else if passed {
+        TaskState::Pass
+    } else {
+        TaskState::Fail
+    };
+
+    let finished_at = now_secs();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    let mut tests = vec![];
+    let mut problems = vec![];
+    for xml in &note.junit {
+        match crate::junit::parse(xml).await {
+            Ok(mut cases) => {
+                mask_cases(&ctx.secrets, &mut cases);
+                tests.extend(cases)
+            }
+            Err(e) => problems.push(format!("couldn't read junit report: {e}")),
+        }
+    }
+    let result = TaskResult {
+        started_at: Some(started_at),
+        finished_at: Some(finished_at),
+        duration_ms: Some(duration_ms),
+        exit_code: note.exit_code.or(exit_code),
+        oom_killed: note.oom_killed,
+        worker: note.worker,
+        tests,
+        artifacts: artifacts_dir
+            .as_deref()
+            .map(list_files)
+            .unwrap_or_default(),
+    };
+    let output = {
+        let mut st = active_run.state.lock().await;
+        match find_task(&mut st, &stage_name, &task_name) {
+            Some(t) => {
+                for problem in problems {
+                    if !t.output.is_empty() {
+                        t.output.push('\n');
+                    }
+                    t.output.push_str(&problem);
+                }
+                t.state = task_state.clone();
+                t.result = result.clone();
+                t.output.clone()
+            }
+            None => String::new(),
+        }
+    };
+
+    let _ = active_run.tx.send(SseEvent {
+        event: "task_result".into(),
+        data: serde_json::to_string(&TaskResultEvent {
+            stage: stage_name,
+            task: task_name,
+            passed,
+            state: task_state.clone(),
+            output,
+            result,
+        })
+        .unwrap(),
+    });
+    ctx.save(&active_run).await;
+    task_state
+}
+
+/// add a line to a task's output and send it to whoever's following the run
+async fn push_output(active_run: &ActiveRun, stage: &str, task: &str, line: &str) {
+    {
+        let mut st = active_run.state.lock().await;
+        if let Some(t) = find_task(&mut st, stage, task) {
+            if !t.output.is_empty() {
+                t.output.push('\n');
+            }
+            t.output.push_str(line);
+        }
+    }
+    let _ = active_run.tx.send(SseEvent {
+        event: "task_output".into(),
+        data: serde_json::to_string(&TaskOutputEvent { stage, task, line }).unwrap(),
+    });
+}
+
+/// what bettertest.run() wrote down about a task
+#[derive(Deserialize, Default)]
+struct Note {
+    worker: Option<String>,
+    /// from the `done` event of the last container that finished
+    exit_code: Option<i32>,
+    #[serde(default)]
+    oom_killed: bool,
+    /// a container hit its timeout
+    #[serde(default)]
+    timed_out: bool,
+    /// raw JUnit XML, one per `run()` that collected a report
+    #[serde(default)]
+    junit: Vec<String>,
+}
+
+/// failure messages quote whatever the test saw, secrets included
+fn mask_cases(secrets: &Secrets, cases: &mut [TestCase]) {
+    for case in cases {
+        case.name = secrets.mask(&case.name);
+        case.message = case.message.as_deref().map(|m| secrets.mask(m));
+    }
+}
+
+/// read and clean up the note. tasks that never called run() don't leave one
+fn read_note(path: &std::path::Path) -> Note {
+    let note = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|n| serde_json::from_str(&n).ok())
+        .unwrap_or_default();
+    let _ = std::fs::remove_file(path);
+    note
+}
+
+/// every file under a directory, as sorted `/`-separated relative paths
+fn list_files(dir: &std::path::Path) -> Vec<String> {
+    fn walk(dir: &std::path::Path, prefix: &str, files: &mut Vec<String>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = format!("{prefix}{name}");
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => walk(&entry.path(), &format!("{path}/"), files),
+                Ok(t) if t.is_file() => files.push(path),
+                _ => {}
+            }
+        }
+    }
+    let mut files = vec![];
+    walk(dir, "", &mut files);
+    files.sort();
+    files
+}
+
+fn find_task<'a>(
+    run: &'a mut PipelineRunState,
+    stage: &str,
+    task: &str,
+) -> Option<&'a mut TaskRunState> {
+    run.stages
+        .iter_mut()
+        .find(|s| s.name == stage)?
+        .tasks
+        .iter_mut()
+        .find(|t| t.name == task)
+}
+
+async fn set_task_state(active_run: &ActiveRun, stage: &str, task: &str, state: TaskState) {
+    let mut st = active_run.state.lock().await;
+    if let Some(t) = find_task(&mut st, stage, task) {
+        t.state = state;
+    }
+}
+
+/// settle a task that never ran (skipped because of a failed dependency or fail-fast)
+async fn finish_untouched(active_run: &ActiveRun, stage: &str, task: &str, state: TaskState) {
+    set_task_state(active_run, stage, task, state.clone()).await;
+    let _ = active_run.tx.send(SseEvent {
+        event: "task_result".into(),
+        data: serde_json::to_string(&TaskResultEvent {
+            stage: stage.into(),
+            task: task.into(),
+            passed: false,
+            state,
+            output: String::new(),
+            result: TaskResult::default(),
+        })
+        .unwrap(),
+    });
+}
+
+/// what a pending task is waiting on. tasks without `@needs` wait for the
+/// whole previous stage to finish, pass or fail — plain sequential stages.
+/// `@needs` replaces that with explicit deps, which all have to pass
+fn deps_of(pipeline: &PipelineDto, stage_idx: usize, task: &str) -> (bool, Vec<TaskId>) {
+    match pipeline.stages[stage_idx].needs.get(task) {
+        Some(needs) => (true, needs.clone()),
+        None if stage_idx == 0 => (false, vec![]),
+        None => {
+            let prev = &pipeline.stages[stage_idx - 1];
+            let deps = prev
+                .tasks
+                .iter()
+                .map(|t| TaskId {
+                    stage: prev.name.clone(),
+                    task: t.clone(),
+                })
+                .collect();
+            (false, deps)
+        }
+    }
+}
+
+/// where a dep stands. one this run doesn't have passed already if the run
+/// left it out on purpose (`excluded`, e.g. what passed before a rerun),
+/// and otherwise never will
+fn dep_state(run: &PipelineRunState, excluded: &[TaskId], dep: &TaskId) -> TaskState {
+    let found = run
+        .stages
+        .iter()
+        .find(|s| s.name == dep.stage)
+        .and_then(|s| s.tasks.iter().find(|t| t.name == dep.task));
+    match found {
+        Some(t) => t.state.clone(),
+        None if excluded.contains(dep) => TaskState::Pass,
+        None => TaskState::Skipped,
+    }
+}
+
+/// pending tasks as (stage index, id), in stage order
+fn pending(pipeline: &PipelineDto, run: &PipelineRunState) -> Vec<(usize, TaskId)> {
+    let mut pending = vec![];
+    for (stage_idx, stage) in pipeline.stages.iter().enumerate() {
+        let Some(stage_run) = run.stages.iter().find(|s| s.name == stage.name) else {
+            continue;
+        };
+        for task in stage_run
+            .tasks
+            .iter()
+            .filter(|t| t.state == TaskState::Pending)
+        {
+            let id = TaskId {
+                stage: stage.name.clone(),
+                task: task.name.clone(),
+            };
+            pending.push((stage_idx, id));
+        }
+    }
+    pending
+}
+
+/// pending tasks that wait on `failed`, directly or through other pending tasks
+fn downstream(pipeline: &PipelineDto, run: &PipelineRunState, failed: TaskId) -> Vec<TaskId> {
+    let mut hit = vec![failed];
+    let mut found = vec![];
+    loop {
+        let more: Vec<TaskId> = pending(pipeline, run)
+            .into_iter()
+            .filter(|(stage_idx, id)| {
+                !found.contains(id)
+                    && deps_of(pipeline, *stage_idx, &id.task)
+                        .1
+                        .iter()
+                        .any(|d| hit.contains(d))
+            })
+            .map(|(_, id)| id)
+            .collect();
+        if more.is_empty() {
+            return found;
+        }
+        hit.extend(more.iter().cloned());
+        found.extend(more);
+    }
+}
+
+struct Schedule {
+    /// (stage index, task) that can start now
+    ready: Vec<(usize, String)>,
+    /// pending tasks that never will start because an explicit dependency didn't pass
+    blocked: Vec<TaskId>,
+}
+
+fn schedule(pipeline: &PipelineDto, run: &PipelineRunState, excluded: &[TaskId]) -> Schedule {
+    let mut ready = vec![];
+    let mut blocked = vec![];
+    for (stage_idx, id) in pending(pipeline, run) {
+        let (explicit, deps) = deps_of(pipeline, stage_idx, &id.task);
+        let states: Vec<TaskState> = deps
+            .iter()
+            .map(|d| dep_state(run, excluded, d))
+            .collect();
+        if states
+            .iter()
+            .any(|s| matches!(s, TaskState::Pending | TaskState::Running))
+        {
+            continue;
+        }
+        if explicit && states.iter().any(|s| *s != TaskState::Pass) {
+            blocked.push(id);
+        } else {
+            ready.push((stage_idx, id.task));
+        }
+    }
+    Schedule { ready, blocked }
+}
+
+/// drive a run to completion: start tasks as their deps settle, skip what
+/// can never run, then mark the run done. `excluded` are tasks the run left
+/// out because they already passed, which `@needs` on them counts as
+pub(crate) async fn run_pipeline(
+    active_run: Arc<ActiveRun>,
+    pipeline: PipelineDto,
+    excluded: Vec<TaskId>,
+    ctx: TaskCtx,
+) {
+    let mut set = tokio::task::JoinSet::new();
+    let mut started_stages = HashSet::new();
+    loop {
+        // skipping a task can unblock (or block) others, so go until it settles
+        while !*active_run.cancel.borrow() {
+            let Schedule { ready, blocked } = {
+                let st = active_run.state.lock().await;
+                schedule(&pipeline, &st, &excluded)
+            };
+            let settled = blocked.is_empty();
+            for id in blocked {
+                finish_untouched(&active_run, &id.stage, &id.task, TaskState::Skipped).await;
+            }
+            for (stage_idx, task) in ready {
+                let stage = &pipeline.stages[stage_idx];
+                set_task_state(&active_run, &stage.name, &task, TaskState::Running).await;
+                if started_stages.insert(stage_idx) {
+                    let _ = active_run.tx.send(SseEvent {
+                        event: "stage_started".into(),
+                        data: serde_json::to_string(&StageEvent { stage: &stage.name }).unwrap(),
+                    });
+                }
+                let _ = active_run.tx.send(SseEvent {
+                    event: "task_started".into(),
+                    data: serde_json::to_string(&TaskEvent {
+                        stage: &stage.name,
+                        task: &task,
+                    })
+                    .unwrap(),
+                });
+                let labels = stage
+                    .labels
+                    .get(&task)
+                    .cloned()
+                    .unwrap_or_default();
+                let fut = run_task(
+                    active_run.clone(),
+                    ctx.clone(),
+                    stage.name.clone(),
+                    task.clone(),
+                    labels,
+                );
+                set.spawn(async move { (stage_idx, task, fut.await) });
+            }
+            if settled {
+                break;
+            }
+        }
+
+        // nothing running and nothing became ready: either everything is
+        // done, the run was cancelled, or what's left can never start
+        let Some(joined) = set.join_next().await else {
+            break;
+        };
+        let Ok((stage_idx, task, task_state)) = joined else {
+            continue;
+        };
+        let stage = &pipeline.stages[stage_idx];
+        if matches!(task_state, TaskState::Fail | TaskState::TimedOut)
+            && stage.fail_fast.unwrap_or(pipeline.fail_fast)
+        {
+            let failed = TaskId {
+                stage: stage.name.clone(),
+                task,
+            };
+            let later = downstream(&pipeline, &*active_run.state.lock().await, failed);
+            for id in later {
+                finish_untouched(&active_run, &id.stage, &id.task, TaskState::Skipped).await;
+            }
+        }
+    }
+
+    if *active_run.cancel.borrow() {
+        {
+            let mut st = active_run.state.lock().await;
+            for task in st
+                .stages
+                .iter_mut()
+                .flat_map(|s| &mut s.tasks)
+                .filter(|t| t.state == TaskState::Pending)
+            {
+                task.state = TaskState::Cancelled;
+            }
+        }
+        let _ = active_run.tx.send(SseEvent {
+            event: "run_cancelled".into(),
+            data: "{}".into(),
+        });
+    }
+
+    // the parser rejects dependency cycles, so this is only a backstop
+    let stuck = pending(&pipeline, &*active_run.state.lock().await);
+    for (_, id) in stuck {
+        finish_untouched(&active_run, &id.stage, &id.task, TaskState::Skipped).await;
+    }
+
+    active_run.state.lock().await.finished_at = Some(now_secs());
+    active_run
+        .active
+        .store(false, Ordering::Relaxed);
+    ctx.save(&active_run).await;
+    let _ = active_run.tx.send(SseEvent {
+        event: "run_done".into(),
+        data: "{}".into(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(name: &str, tasks: &[&str]) -> StageDto {
+        StageDto {
+            name: name.into(),
+            tasks: tasks.iter().map(|t| t.to_string()).collect(),
+            fail_fast: None,
+            needs: Default::default(),
+            labels: Default::default(),
+        }
+    }
+
+    fn set(run: &mut PipelineRunState, stage: &str, task: &str, state: TaskState) {
+        find_task(run, stage, task).unwrap().state = state;
+    }
+
+    #[test]
+    fn schedule_follows_stages_and_needs() {
+        let mut integration = stage("Integration", &["task_api", "task_plain"]);
+        integration.needs.insert(
+            "task_api".into(),
+            vec![TaskId {
+                stage: "Unit".into(),
+                task: "task_a".into(),
+            }],
+        );
+        let pipeline = PipelineDto {
+            stages: vec![stage("Unit", &["task_a", "task_b"]), integration],
+            fail_fast: false,
+        };
+        let mut run = initial_run_state(&pipeline, 1);
+
+        let first = schedule(&pipeline, &run, &[]);
+        assert_eq!(
+            first.ready,
+            vec![(0, "task_a".to_string()), (0, "task_b".to_string())]
+        );
+        assert!(first.blocked.is_empty());
+
+        // task_api only waits on task_a, task_plain on the whole stage
+        set(&mut run, "Unit", "task_a", TaskState::Fail);
+        set(&mut run, "Unit", "task_b", TaskState::Running);
+        let second = schedule(&pipeline, &run, &[]);
+        assert!(second.ready.is_empty());
+        assert_eq!(
+            second.blocked,
+            vec![TaskId {
+                stage: "Integration".into(),
+                task: "task_api".into(),
+            }]
+        );
+
+        set(&mut run, "Unit", "task_b", TaskState::Pass);
+        set(&mut run, "Integration", "task_api", TaskState::Skipped);
+        let third = schedule(&pipeline, &run, &[]);
+        assert_eq!(third.ready, vec![(1, "task_plain".to_string())]);
+    }
+
+    fn id(stage: &str, task: &str) -> TaskId {
+        TaskId {
+            stage: stage.into(),
+            task: task.into(),
+        }
+    }
+
+    #[test]
+    fn reruns_only_count_excluded_deps_as_passed() {
+        let mut integration = stage("Integration", &["task_api", "task_db"]);
+        integration
+            .needs
+            .insert("task_api".into(), vec![id("Unit", "task_a")]);
+        integration
+            .needs
+            .insert("task_db".into(), vec![id("Unit", "task_b")]);
+        // a rerun of just Integration, where Unit.task_a passed before
+        let pipeline = PipelineDto {
+            stages: vec![integration],
+            fail_fast: false,
+        };
+        let run = initial_run_state(&pipeline, 2);
+        let scheduled = schedule(&pipeline, &run, &[id("Unit", "task_a")]);
+        assert_eq!(scheduled.ready, vec![(0, "task_api".to_string())]);
+        assert_eq!(scheduled.blocked, vec![id("Integration", "task_db")]);
+    }
+
+    #[test]
+    fn fail_fast_skips_only_whats_downstream() {
+        let mut integration = stage("Integration", &["task_api", "task_plain"]);
+        integration
+            .needs
+            .insert("task_api".into(), vec![id("Lint", "task_lint")]);
+        let pipeline = PipelineDto {
+            stages: vec![
+                stage("Lint", &["task_lint"]),
+                stage("Unit", &["task_a", "task_b"]),
+                integration,
+                stage("Deploy", &["task_ship"]),
+            ],
+            fail_fast: true,
+        };
+        let mut run = initial_run_state(&pipeline, 1);
+        set(&mut run, "Lint", "task_lint", TaskState::Pass);
+        set(&mut run, "Unit", "task_a", TaskState::Fail);
+        set(&mut run, "Unit", "task_b", TaskState::Running);
+        // task_api only needs Lint, so it goes on
+        assert_eq!(
+            downstream(&pipeline, &run, id("Unit", "task_a")),
+            vec![id("Integration", "task_plain"), id("Deploy", "task_ship")]
+        );
+    }
+
+    #[test]
+    fn junit_messages_are_masked() {
+        let path = std::env::temp_dir().join(format!("bettertest_mask_{}", std::process::id()));
+        std::fs::write(&path, "API_KEY=hunter2\n").unwrap();
+        let secrets = Secrets::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let mut cases = vec![TestCase {
+            class: "test.test_api".into(),
+            name: "test_auth[hunter2]".into(),
+            status: TestStatus::Failed,
+            duration_ms: None,
+            message: Some("assert 'hunter2' == 'hunter3'".into()),
+        }];
+        mask_cases(&secrets, &mut cases);
+        assert_eq!(cases[0].name, "test_auth[***]");
+        assert_eq!(
+            cases[0].message.as_deref(),
+            Some("assert '***' == 'hunter3'")
+        );
+    }
+
+    #[test]
+    fn list_files_walks_subdirectories() {
+        let dir = std::env::temp_dir().join(format!("bettertest_list_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("htmlcov/js")).unwrap();
+        std::fs::write(dir.join("htmlcov/index.html"), "").unwrap();
+        std::fs::write(dir.join("htmlcov/js/app.js"), "").unwrap();
+        std::fs::write(dir.join("app.whl"), "").unwrap();
+        let files = list_files(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            files,
+            vec!["app.whl", "htmlcov/index.html", "htmlcov/js/app.js"]
+        );
+        assert_eq!(list_files(&dir), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// values shorter than this aren't masked: `***` in place of every `1` or
+/// `on` in the output would hide more than the secret
+const MIN_MASKED: usize = 4;
+
+/// named secrets the boss hands to pipedefs, loaded from a `NAME=value` file.
+/// the values never go over the wire to the frontend — task output is masked
+#[derive(Default)]
+pub(crate) struct Secrets {
+    values: BTreeMap<String, String>,
+    /// what `mask` looks for: every value, plus each line of the multi-line
+    /// ones, since output gets masked a line at a time
+    masked: Vec<String>,
+}
+
+impl Secrets {
+    /// a value in double quotes can hold newlines as `\n`, e.g. for a PEM key
+    pub(crate) fn load(path: &Path) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read secrets file {}: {e}", path.display()));
+        let mut values = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                panic!("{}:{}: expected NAME=value", path.display(), i + 1);
+            };
+            let value = value.trim();
+            let value = match value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+            {
+                Some(quoted) => quoted.replace("\\n", "\n"),
+                None => value.to_string(),
+            };
+            values.insert(name.trim().to_string(), value);
+        }
+        Self::new(values)
+    }
+
+    fn new(values: BTreeMap<String, String>) -> Self {
+        let mut masked = vec![];
+        for (name, value) in &values {
+            if value.chars().count() < MIN_MASKED {
+                println!("warning: secret {name} is too short to be masked in task output");
+                continue;
+            }
+            masked.push(value.clone());
+            masked.extend(
+                value
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| l.chars().count() >= MIN_MASKED)
+                    .map(String::from),
+            );
+        }
+        Secrets { values, masked }
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
+    /// handed to run_task.py as BETTERTEST_SECRETS, read by bettertest.secret().
+    /// every task gets all of them, whichever it actually uses
+    pub(crate) fn to_json(&self) -> String {
+        serde_json::to_string(&self.values).unwrap()
+    }
+
+    /// `***` over every secret in `line`. where two of them overlap, the
+    /// whole stretch they cover goes
+    pub(crate) fn mask(&self, line: &str) -> String {
+        let mut spans: Vec<(usize, usize)> = self
+            .masked
+            .iter()
+            .flat_map(|value| {
+                line.match_indices(value.as_str())
+                    .map(|(start, m)| (start, start + m.len()))
+            })
+            .collect();
+        if spans.is_empty() {
+            return line.to_string();
+        }
+        spans.sort();
+        let mut merged: Vec<(usize, usize)> = vec![];
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        let mut masked = String::new();
+        let mut done = 0;
+        for (start, end) in merged {
+            masked.push_str(&line[done..start]);
+            masked.push_str("***");
+            done = end;
+        }
+        masked.push_str(&line[done..]);
+        masked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets(values: &[(&str, &str)]) -> Secrets {
+        Secrets::new(
+            values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn mask_single_line_values() {
+        let s = secrets(&[("API_KEY", "hunter2")]);
+        assert_eq!(s.mask("key=hunter2, again hunter2"), "key=***, again ***");
+        assert_eq!(s.mask("nothing here"), "nothing here");
+    }
+
+    #[test]
+    fn mask_each_line_of_multi_line_values() {
+        let path = std::env::temp_dir().join(format!("bettertest_secrets_{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "KEY=\"-----BEGIN KEY-----\\nMIIEvQIBADANBg\\n-----END KEY-----\"\n",
+        )
+        .unwrap();
+        let s = Secrets::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(s.to_json().contains("MIIEvQIBADANBg\\n"));
+        assert_eq!(s.mask("got MIIEvQIBADANBg back"), "got *** back");
+        assert_eq!(s.mask("-----END KEY-----"), "***");
+    }
+
+    #[test]
+    fn mask_overlapping_values_whole() {
+        let s = secrets(&[("A", "abcdef"), ("B", "defghi"), ("C", "bcde")]);
+        assert_eq!(s.mask("xabcdefghix"), "x***x");
+        assert_eq!(s.mask("abcd-bcde"), "abcd-***");
+    }
+
+    #[test]
+    fn short_values_are_not_masked() {
+        let s = secrets(&[("DEBUG", "1"), ("TOKEN", "s3cr3t")]);
+        assert_eq!(s.mask("DEBUG=1 TOKEN=s3cr3t"), "DEBUG=1 TOKEN=***");
+        assert!(s.to_json().contains("\"DEBUG\":\"1\""));
+    }
+}
@@ -0,0 +1,289 @@
+use bettertest_common::*;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// github-style commit status, the check mark a forge shows next to a commit
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct CommitStatus {
+    pub state: StatusState,
+    pub target_url: String,
+    pub description: String,
+    pub context: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StatusState {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
+/// somewhere to send commit statuses. reporting is best effort: failures are
+/// logged, never surfaced to the run
+pub(crate) trait StatusReporter: Send + Sync {
+    fn report<'a>(&'a self, sha: &'a str, status: &'a CommitStatus) -> BoxFuture<'a, ()>;
+}
+
+/// what a finished run looks like on the forge
+pub(crate) fn final_status(run: &PipelineRunState) -> (StatusState, String) {
+    let tasks: Vec<_> = run
+        .stages
+        .iter()
+        .flat_map(|s| &s.tasks)
+        .collect();
+    let count = |f: fn(&TaskState) -> bool| tasks.iter().filter(|t| f(&t.state)).count();
+    let failed = count(|s| matches!(s, TaskState::Fail | TaskState::TimedOut));
+    let passed = count(|s| *s == TaskState::Pass);
+    if count(|s| *s == TaskState::Cancelled) > 0 {
+        (StatusState::Error, "cancelled".into())
+    } else if failed > 0 {
+        (
+            StatusState::Failure,
+            format!("{failed} of {} tasks failed", tasks.len()),
+        )
+    } else if passed == 0 {
+        // everything skipped: nothing was tested, which isn't a pass
+        (
+            StatusState::Failure,
+            format!("none of {} tasks passed", tasks.len()),
+        )
+    } else {
+        (
+            StatusState::Success,
+            format!("{passed} of {} tasks passed", tasks.len()),
+        )
+    }
+}
+
+/// posts statuses to a github-compatible API (github, gitea, forgejo)
+pub(crate) struct ForgeReporter {
+    client: reqwest::Client,
+    /// e.g. `https://api.github.com/repos/OWNER/REPO/statuses/{sha}`
+    url_template: String,
+    token: Option<String>,
+    attempts: u32,
+    /// doubles after each failed attempt
+    backoff: Duration,
+}
+
+impl ForgeReporter {
+    pub(crate) fn new(url_template: String, token: Option<String>) -> Self {
+        ForgeReporter {
+            client: reqwest::Client::new(),
+            url_template,
+            token,
+            attempts: 4,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// on failure: whether it's worth trying again, and what went wrong
+    async fn post(&self, url: &str, status: &CommitStatus) -> Result<(), (bool, String)> {
+        let mut req = self
+            .client
+            .post(url)
+            .header(reqwest::header::USER_AGENT, "bettertest")
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .timeout(Duration::from_secs(10))
+            .json(status);
+        if let Some(token) = &self.token {
+            req = req.bearer_auth(token);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| (true, e.to_string()))?;
+        let code = resp.status();
+        if code.is_success() {
+            return Ok(());
+        }
+        // rate limits and server errors might clear up, bad tokens and urls won't
+        let retry = code.is_server_error() || code == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        Err((retry, format!("forge returned {code}")))
+    }
+}
+
+impl StatusReporter for ForgeReporter {
+    fn report<'a>(&'a self, sha: &'a str, status: &'a CommitStatus) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let url = self.url_template.replace("{sha}", sha);
+            let mut backoff = self.backoff;
+            for attempt in 1..=self.attempts {
+                match self.post(&url, status).await {
+                    Ok(()) => return,
+                    Err((retry, e)) => {
+                        eprintln!("status report for {sha} failed (attempt {attempt}): {e}");
+                        if !retry {
+                            return;
+                        }
+                    }
+                }
+                if attempt < self.attempts {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Json, Router,
+        extract::{Path, State},
+        http::{HeaderMap, StatusCode},
+        routing::post,
+    };
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Mock {
+        /// status codes to answer with before succeeding
+        failures: Mutex<Vec<StatusCode>>,
+        /// (sha, authorization header, body) of every request
+        received: Mutex<Vec<(String, Option<String>, CommitStatus)>>,
+    }
+
+    async fn statuses(
+        State(mock): State<Arc<Mock>>,
+        Path(sha): Path<String>,
+        headers: HeaderMap,
+        Json(status): Json<CommitStatus>,
+    ) -> StatusCode {
+        let auth = headers
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        mock.received
+            .lock()
+            .unwrap()
+            .push((sha, auth, status));
+        let mut failures = mock.failures.lock().unwrap();
+        if failures.is_empty() {
+            StatusCode::CREATED
+        } else {
+            failures.remove(0)
+        }
+    }
+
+    /// a fake forge on a random port, plus a reporter pointed at it
+    async fn mock_forge(failures: Vec<StatusCode>) -> (Arc<Mock>, ForgeReporter) {
+        let mock = Arc::new(Mock {
+            failures: Mutex::new(failures),
+            ..Default::default()
+        });
+        let app = Router::new()
+            .route("/repos/you/project/statuses/{sha}", post(statuses))
+            .with_state(mock.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut reporter = ForgeReporter::new(
+            format!("http://{addr}/repos/you/project/statuses/{{sha}}"),
+            Some("t0ken".into()),
+        );
+        reporter.backoff = Duration::from_millis(1);
+        (mock, reporter)
+    }
+
+    fn pending() -> CommitStatus {
+        CommitStatus {
+            state: StatusState::Pending,
+            target_url: "http://boss:9001/?run=3".into(),
+            description: "running Unit".into(),
+            context: "bettertest".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_status_to_forge() {
+        let (mock, reporter) = mock_forge(vec![]).await;
+        reporter.report("abc123", &pending()).await;
+
+        let received = mock.received.lock().unwrap();
+        assert_eq!(
+            *received,
+            vec![(
+                "abc123".to_string(),
+                Some("Bearer t0ken".to_string()),
+                pending()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (mock, reporter) =
+            mock_forge(vec![StatusCode::BAD_GATEWAY, StatusCode::TOO_MANY_REQUESTS]).await;
+        reporter.report("abc123", &pending()).await;
+        assert_eq!(mock.received.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (mock, reporter) = mock_forge(vec![StatusCode::INTERNAL_SERVER_ERROR; 10]).await;
+        reporter.report("abc123", &pending()).await;
+        assert_eq!(mock.received.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_client_errors() {
+        let (mock, reporter) = mock_forge(vec![StatusCode::UNAUTHORIZED]).await;
+        reporter.report("abc123", &pending()).await;
+        assert_eq!(mock.received.lock().unwrap().len(), 1);
+    }
+
+    fn run_with(states: &[TaskState]) -> PipelineRunState {
+        PipelineRunState {
+            run_id: 1,
+            active: false,
+            stages: vec![StageRunState {
+                name: "Unit".into(),
+                tasks: states
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| TaskRunState {
+                        name: format!("task_{i}"),
+                        state: s.clone(),
+                        output: String::new(),
+                        result: TaskResult::default(),
+                    })
+                    .collect(),
+            }],
+            started_at: None,
+            finished_at: None,
+            parent_run_id: None,
+            queued: false,
+            git: None,
+        }
+    }
+
+    #[test]
+    fn final_status_of_runs() {
+        use TaskState::*;
+        assert_eq!(
+            final_status(&run_with(&[Pass, Pass, Skipped])),
+            (StatusState::Success, "2 of 3 tasks passed".into())
+        );
+        assert_eq!(
+            final_status(&run_with(&[Pass, Fail, TimedOut])),
+            (StatusState::Failure, "2 of 3 tasks failed".into())
+        );
+        assert_eq!(
+            final_status(&run_with(&[Fail, Cancelled])),
+            (StatusState::Error, "cancelled".into())
+        );
+        assert_eq!(
+            final_status(&run_with(&[Skipped, Skipped])),
+            (StatusState::Failure, "none of 2 tasks passed".into())
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use bettertest_common::*;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// one json file per run: `<dir>/<run_id>.json`, its `RunSummary` in
+/// `<dir>/<run_id>.summary.json` for listing, and its tasks' artifacts
+/// under `<dir>/<run_id>/`
+#[derive(Clone)]
+pub(crate) struct RunStore {
+    dir: PathBuf,
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl RunStore {
+    pub(crate) fn open(dir: PathBuf) -> Self {
+        std::fs::create_dir_all(&dir).expect("failed to create run history dir");
+        RunStore { dir }
+    }
+
+    pub(crate) fn dir(&self) -> &std::path::Path {
+        &self.dir
+    }
+
+    pub(crate) fn artifacts_dir(&self, run_id: u32) -> PathBuf {
+        self.dir.join(run_id.to_string())
+    }
+
+    fn path_for(&self, run_id: u32) -> PathBuf {
+        self.dir.join(format!("{run_id}.json"))
+    }
+
+    fn summary_path(&self, run_id: u32) -> PathBuf {
+        self.dir.join(format!("{run_id}.summary.json"))
+    }
+
+    fn ids(&self) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                e.file_name()
+                    .to_str()?
+                    .strip_suffix(".json")?
+                    .parse()
+                    .ok()
+            })
+            .collect()
+    }
+
+    pub(crate) fn max_run_id(&self) -> u32 {
+        self.ids().into_iter().max().unwrap_or(0)
+    }
+
+    /// blocking. callers saving the same run from several tasks have to take
+    /// turns (see scheduler::persist), or an older snapshot can land last
+    pub(crate) fn save(&self, run: &PipelineRunState) -> std::io::Result<()> {
+        write_atomic(&self.path_for(run.run_id), &serde_json::to_vec(run)?)?;
+        write_atomic(
+            &self.summary_path(run.run_id),
+            &serde_json::to_vec(&RunSummary::of(run))?,
+        )
+    }
+
+    /// stored runs are never active or queued — if the boss restarted, that run is dead
+    pub(crate) fn get(&self, run_id: u32) -> Option<PipelineRunState> {
+        let bytes = std::fs::read(self.path_for(run_id)).ok()?;
+        let mut run: PipelineRunState = serde_json::from_slice(&bytes).ok()?;
+        run.active = false;
+        run.queued = false;
+        Some(run)
+    }
+
+    /// newest first. runs saved before summaries existed get read in full
+    pub(crate) fn list(&self) -> Vec<RunSummary> {
+        let mut ids = self.ids();
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        ids.into_iter()
+            .filter_map(|id| {
+                let summary = std::fs::read(self.summary_path(id))
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<RunSummary>(&bytes).ok());
+                match summary {
+                    Some(mut s) => {
+                        s.active = false;
+                        s.queued = false;
+                        Some(s)
+                    }
+                    None => self.get(id).map(|r| RunSummary::of(&r)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// write then rename so a crash mid-write never leaves a truncated file. the
+/// temp name is unique so concurrent writers never share one
+fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let tmp = path.with_file_name(format!(
+        ".{name}.{}.{}.tmp",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_reads_summaries() {
+        let dir = std::env::temp_dir().join(format!("bettertest_store_{}", std::process::id()));
+        let store = RunStore::open(dir.clone());
+        let pipeline = PipelineDto {
+            stages: vec![StageDto {
+                name: "Unit".into(),
+                tasks: vec!["task_a".into(), "task_b".into()],
+                fail_fast: None,
+                needs: Default::default(),
+                labels: Default::default(),
+            }],
+            fail_fast: false,
+        };
+        let mut run = crate::scheduler::initial_run_state(&pipeline, 7);
+        run.stages[0].tasks[0].state = TaskState::Pass;
+        store.save(&run).unwrap();
+        // a run from before summaries were written
+        run.run_id = 3;
+        std::fs::write(store.path_for(3), serde_json::to_vec(&run).unwrap()).unwrap();
+
+        let listed: Vec<_> = store
+            .list()
+            .iter()
+            .map(|s| (s.run_id, s.active, s.passed, s.total))
+            .collect();
+        let leftovers = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            })
+            .count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(listed, vec![(7, false, 1, 2), (3, false, 1, 2)]);
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,145 @@
+/// a regular file out of a tar archive, which is how docker hands out the
+/// contents of a container
+#[derive(Debug, PartialEq)]
+pub(crate) struct File {
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+const BLOCK: usize = 512;
+
+/// the regular files in an archive. directories, links and the like are
+/// skipped; anything that stops looking like tar ends the list
+pub(crate) fn files(archive: &[u8]) -> Vec<File> {
+    let mut files = vec![];
+    // from a GNU long name or pax header, for the entry that follows it
+    let mut next_path = None;
+    let mut offset = 0;
+    while offset + BLOCK <= archive.len() {
+        let header = &archive[offset..offset + BLOCK];
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+        let Some(size) = octal(&header[124..136]) else {
+            break;
+        };
+        let start = offset + BLOCK;
+        let Some(data) = archive.get(start..start + size) else {
+            break;
+        };
+        offset = start + size.div_ceil(BLOCK) * BLOCK;
+
+        match header[156] {
+            b'L' => next_path = Some(text(data)),
+            b'x' => next_path = pax_path(data).or(next_path),
+            b'0' | 0 => {
+                let path = next_path.take().unwrap_or_else(|| {
+                    let name = text(&header[..100]);
+                    let prefix = text(&header[345..500]);
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{prefix}/{name}")
+                    } else {
+                        name
+                    }
+                });
+                files.push(File {
+                    path,
+                    data: data.to_vec(),
+                });
+            }
+            _ => next_path = None,
+        }
+    }
+    files
+}
+
+/// a NUL-padded header field
+fn text(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn octal(field: &[u8]) -> Option<usize> {
+    let digits = text(field);
+    usize::from_str_radix(digits.trim(), 8).ok()
+}
+
+/// `path` out of pax records, which look like `<len> <key>=<value>\n`
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records)
+        .lines()
+        .find_map(|r| r.split_once(' ')?.1.strip_prefix("path="))
+        .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, kind: u8, data: &[u8]) -> Vec<u8> {
+        let mut header = vec![0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let size = format!("{:011o}", data.len());
+        header[124..135].copy_from_slice(size.as_bytes());
+        header[156] = kind;
+        header[257..263].copy_from_slice(b"ustar\0");
+        let mut out = header;
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(BLOCK) * BLOCK, 0);
+        out
+    }
+
+    fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = entries.concat();
+        out.extend_from_slice(&[0; BLOCK * 2]);
+        out
+    }
+
+    #[test]
+    fn reads_files_and_skips_directories() {
+        let tar = archive(&[
+            entry("reports/", b'5', b""),
+            entry("reports/junit.xml", b'0', b"<testsuite/>"),
+            entry("reports/empty", b'0', b""),
+        ]);
+        assert_eq!(
+            files(&tar),
+            vec![
+                File {
+                    path: "reports/junit.xml".into(),
+                    data: b"<testsuite/>".to_vec(),
+                },
+                File {
+                    path: "reports/empty".into(),
+                    data: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_names_from_gnu_and_pax_headers() {
+        let long = format!("{}/report.xml", "deep".repeat(40));
+        let tar = archive(&[
+            entry("././@LongLink", b'L', format!("{long}\0").as_bytes()),
+            entry("truncated", b'0', b"a"),
+            entry("PaxHeader", b'x', b"30 path=pax/named/report.xml\n"),
+            entry("truncated", b'0', b"b"),
+        ]);
+        let paths: Vec<_> = files(&tar)
+            .into_iter()
+            .map(|f| f.path)
+            .collect();
+        assert_eq!(paths, vec![long, "pax/named/report.xml".to_string()]);
+    }
+
+    #[test]
+    fn truncated_archive_stops_cleanly() {
+        let mut tar = entry("big", b'0', &[7; 1000]);
+        tar.truncate(BLOCK + 10);
+        assert_eq!(files(&tar), vec![]);
+    }
+}
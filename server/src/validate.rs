@@ -0,0 +1,50 @@
+use crate::pipedef;
+use std::path::Path;
+use std::process::ExitCode;
+
+/// `bettertest validate`: parse the pipedef like the boss would, then look for
+/// mistakes that parse fine. prints `path:line: message` per problem
+pub fn run(path: &Path) -> ExitCode {
+    let shown = path.display();
+    let pipeline = match pipedef::parse(path) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            let dto = e.to_dto();
+            match dto.line {
+                Some(line) => eprintln!("{shown}:{line}: {}", dto.summary),
+                None => eprintln!("{shown}: {}", dto.summary),
+            }
+            eprintln!("\n{}", dto.details);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let problems = match pipedef::problems(path) {
+        Ok(problems) => problems,
+        Err(e) => {
+            eprintln!("{shown}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    for p in &problems {
+        match p.line {
+            Some(line) => eprintln!("{shown}:{line}: {}", p.message),
+            None => eprintln!("{shown}: {}", p.message),
+        }
+    }
+    if !problems.is_empty() {
+        eprintln!("{} problem(s)", problems.len());
+        return ExitCode::FAILURE;
+    }
+
+    let tasks: usize = pipeline
+        .stages
+        .iter()
+        .map(|s| s.tasks.len())
+        .sum();
+    println!(
+        "{shown}: ok, {} stages, {tasks} tasks",
+        pipeline.stages.len()
+    );
+    ExitCode::SUCCESS
+}
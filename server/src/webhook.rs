@@ -0,0 +1,156 @@
+use hmac::{Hmac, KeyInit, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+/// header github signs push deliveries with
+pub(crate) const GITHUB_SIGNATURE: &str = "x-hub-signature-256";
+/// same `sha256=<hex hmac of the body>` scheme, for anything that isn't github
+pub(crate) const GENERIC_SIGNATURE: &str = "x-bettertest-signature";
+
+/// checks a `sha256=<hex>` signature header against the raw request body
+pub(crate) fn verify(secret: &str, body: &[u8], header: &str) -> bool {
+    let Some(sig) = header
+        .strip_prefix("sha256=")
+        .and_then(|h| hex::decode(h).ok())
+    else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    // constant time, so the signature can't be guessed a byte at a time
+    mac.verify_slice(&sig).is_ok()
+}
+
+/// a pushed commit, from either hook
+#[derive(Debug, PartialEq)]
+pub(crate) struct Push {
+    pub repo: String,
+    pub git_ref: String,
+    /// github always sends one. generic pushes without it get resolved by the boss
+    pub sha: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubPush {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    #[serde(default)]
+    deleted: bool,
+    repository: GithubRepo,
+}
+
+#[derive(Deserialize)]
+struct GithubRepo {
+    clone_url: String,
+}
+
+/// None for pushes that have nothing to test, i.e. a deleted branch
+pub(crate) fn parse_github_push(body: &[u8]) -> Result<Option<Push>, serde_json::Error> {
+    let push: GithubPush = serde_json::from_slice(body)?;
+    if push.deleted {
+        return Ok(None);
+    }
+    Ok(Some(Push {
+        repo: push.repository.clone_url,
+        git_ref: push.git_ref,
+        sha: Some(push.after),
+    }))
+}
+
+#[derive(Deserialize)]
+struct GenericPush {
+    repo: String,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    sha: Option<String>,
+}
+
+/// `{"repo": ..., "ref": ..., "sha": ...}`, sha optional
+pub(crate) fn parse_generic_push(body: &[u8]) -> Result<Push, serde_json::Error> {
+    let push: GenericPush = serde_json::from_slice(body)?;
+    Ok(Push {
+        repo: push.repo,
+        git_ref: push.git_ref,
+        sha: push.sha,
+    })
+}
+
+/// the `sha256=<hex>` header value `verify` accepts, for signing our own requests
+pub(crate) fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_accepts_matching_signature() {
+        let body = br#"{"ref":"refs/heads/main"}"#;
+        assert!(verify("hunter2", body, &sign("hunter2", body)));
+    }
+
+    #[test]
+    fn verify_rejects_wrong_secret_or_body() {
+        let body = br#"{"ref":"refs/heads/main"}"#;
+        assert!(!verify("hunter2", body, &sign("other", body)));
+        assert!(!verify("hunter2", b"{}", &sign("hunter2", body)));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_header() {
+        let body = b"{}";
+        let sig = sign("hunter2", body);
+        assert!(!verify("hunter2", body, sig.trim_start_matches("sha256=")));
+        assert!(!verify("hunter2", body, "sha256=not-hex"));
+        assert!(!verify("hunter2", body, ""));
+    }
+
+    #[test]
+    fn parse_github_push_event() {
+        let body = br#"{
+            "ref": "refs/heads/main",
+            "before": "0000000000000000000000000000000000000000",
+            "after": "0123456789abcdef0123456789abcdef01234567",
+            "deleted": false,
+            "repository": {"clone_url": "https://github.com/you/project.git"}
+        }"#;
+        assert_eq!(
+            parse_github_push(body).unwrap(),
+            Some(Push {
+                repo: "https://github.com/you/project.git".into(),
+                git_ref: "refs/heads/main".into(),
+                sha: Some("0123456789abcdef0123456789abcdef01234567".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_github_branch_delete_is_ignored() {
+        let body = br#"{
+            "ref": "refs/heads/gone",
+            "after": "0000000000000000000000000000000000000000",
+            "deleted": true,
+            "repository": {"clone_url": "https://github.com/you/project.git"}
+        }"#;
+        assert_eq!(parse_github_push(body).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_generic_push_without_sha() {
+        let body = br#"{"repo": "/srv/git/project.git", "ref": "main"}"#;
+        assert_eq!(
+            parse_generic_push(body).unwrap(),
+            Push {
+                repo: "/srv/git/project.git".into(),
+                git_ref: "main".into(),
+                sha: None,
+            }
+        );
+    }
+}
@@ -1,39 +1,410 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use axum::{
     Json, Router,
-    response::sse::{Event, Sse},
-    routing::{get, post},
+    extract::{Path, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, KeepAliveStream, Sse},
+    },
+    routing::{delete, get, post},
 };
+use base64::prelude::*;
 use bollard::Docker;
-use bollard::query_parameters::CreateContainerOptions;
-use bollard::secret::ContainerCreateBody;
+use bollard::models::{ContainerCreateBody, HostConfig};
+use bollard::query_parameters::{
+    CreateContainerOptions, DownloadFromContainerOptions, RemoveContainerOptions,
+};
 use futures::StreamExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc, watch};
 use tokio_stream::wrappers::ReceiverStream;
 
-type EventStream = Sse<ReceiverStream<Result<Event, Infallible>>>;
+type EventStream = Sse<KeepAliveStream<ReceiverStream<Result<Event, Infallible>>>>;
+
+pub struct WorkerOptions {
+    /// containers allowed to run at once. the rest of the /run requests wait.
+    /// None starts every one straight away
+    pub max_containers: Option<usize>,
+    /// what this box has to offer, e.g. `mem=high`. shown at /info so a boss's
+    /// pool can route `@requires` tasks here
+    pub labels: BTreeMap<String, String>,
+    /// bearer token every route but /health requires. None leaves the API open
+    pub token: Option<String>,
+}
+
+/// where the worker token comes from when there's no --worker-token file.
+/// the boss hands it to task processes the same way
+pub(crate) const TOKEN_ENV: &str = "BETTERTEST_WORKER_TOKEN";
+
+/// the shared worker token: the --worker-token file if given, else $BETTERTEST_WORKER_TOKEN
+pub(crate) fn load_token(path: Option<&std::path::Path>) -> Option<String> {
+    let token = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("failed to read worker token {}: {e}", path.display())),
+        None => std::env::var(TOKEN_ENV).ok()?,
+    };
+    let token = token.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+/// whether an Authorization header carries the token. compares hashes, so how
+/// long it takes doesn't give away how much of a guess was right
+fn authorized(token: &str, header: Option<&str>) -> bool {
+    let Some(given) = header.and_then(|h| h.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let expected = Sha256::digest(token.as_bytes());
+    let given = Sha256::digest(given.trim().as_bytes());
+    expected
+        .iter()
+        .zip(given.iter())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+/// middleware for routes only holders of the worker token may use
+pub(crate) async fn require_token(
+    State(token): State<Arc<String>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let auth = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok());
+    if !authorized(&token, auth) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "missing or wrong worker token\n",
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+struct WorkerState {
+    docker: Docker,
+    /// containers started by this worker that haven't been removed yet —
+    /// the kill endpoint only touches these, not arbitrary containers on the host
+    containers: Mutex<HashSet<String>>,
+    /// one permit per container allowed to run at the same time
+    slots: Arc<Semaphore>,
+    /// None when there's no --max-containers
+    capacity: Option<usize>,
+    waiting: SlotQueue,
+    labels: BTreeMap<String, String>,
+}
+
+/// /run requests waiting for a slot, in the order they'll get one (the
+/// semaphore hands out permits first come first served)
+#[derive(Default)]
+struct SlotQueue {
+    tickets: Mutex<VecDeque<u64>>,
+    next_ticket: AtomicU64,
+    /// pinged whenever someone joins or leaves, so the rest can report their new position
+    changed: watch::Sender<()>,
+}
+
+impl SlotQueue {
+    fn join(&self) -> u64 {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.tickets.lock().unwrap().push_back(ticket);
+        self.changed.send_replace(());
+        ticket
+    }
+
+    fn leave(&self, ticket: u64) {
+        self.tickets
+            .lock()
+            .unwrap()
+            .retain(|t| *t != ticket);
+        self.changed.send_replace(());
+    }
+
+    /// 1 is next in line
+    fn position(&self, ticket: u64) -> Option<usize> {
+        self.tickets
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|t| *t == ticket)
+            .map(|i| i + 1)
+    }
+
+    fn len(&self) -> usize {
+        self.tickets.lock().unwrap().len()
+    }
+}
+
+#[derive(Serialize)]
+struct InfoResponse {
+    labels: BTreeMap<String, String>,
+    capacity: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    running: usize,
+    queued: usize,
+    capacity: Option<usize>,
+}
 
 #[derive(Deserialize)]
 pub struct RunRequest {
     image: String,
     command: String,
+    /// kill the container if it's still running after this long
+    timeout_secs: Option<u64>,
+    #[serde(default)]
+    limits: ResourceLimits,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    checkout: Option<Checkout>,
+    /// path of a JUnit XML report inside the container, sent back in a `junit`
+    /// event once the command exits
+    junit: Option<String>,
+    /// files or directories inside the container, each sent back in `artifact`
+    /// events once the command exits
+    #[serde(default)]
+    artifacts: Vec<String>,
+}
+
+/// test this commit instead of whatever the image was built from. the image's
+/// workdir has to be a clone of `repo`, like /app in the Dockerfile template
+#[derive(Deserialize)]
+pub struct Checkout {
+    repo: String,
+    sha: String,
+}
+
+impl Checkout {
+    /// prefix the command with a fetch + checkout. repo and sha go in as env
+    /// vars so the shell never has to parse them
+    fn wrap(&self, command: String, env: &mut HashMap<String, String>) -> String {
+        env.insert("BETTERTEST_REPO".into(), self.repo.clone());
+        env.insert("BETTERTEST_SHA".into(), self.sha.clone());
+        format!(
+            "git fetch --quiet -- \"$BETTERTEST_REPO\" \"$BETTERTEST_SHA\" \
+             && git checkout --quiet --force \"$BETTERTEST_SHA\" \
+             && {command}"
+        )
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct ResourceLimits {
+    /// fractional cpus, e.g. 1.5
+    cpus: Option<f64>,
+    memory_bytes: Option<i64>,
+    pids: Option<i64>,
+    shm_size_bytes: Option<i64>,
+}
+
+impl ResourceLimits {
+    fn host_config(&self) -> HostConfig {
+        HostConfig {
+            nano_cpus: self.cpus.map(|c| (c * 1e9) as i64),
+            memory: self.memory_bytes,
+            pids_limit: self.pids,
+            shm_size: self.shm_size_bytes,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DoneEvent {
+    exit_code: i64,
+    oom_killed: bool,
+}
+
+#[derive(Serialize)]
+struct ArtifactEvent {
+    /// relative to the collected path's parent, e.g. `htmlcov/index.html`
+    path: String,
+    /// base64
+    data: String,
+}
+
+/// most a single artifact file may be. bigger ones are skipped with a log line
+const ARTIFACT_FILE_LIMIT: usize = 32 << 20;
+/// most all the artifacts of one task may add up to, so a task can't fill the
+/// boss's disk or memory with whatever it leaves lying around
+const ARTIFACTS_LIMIT: usize = 128 << 20;
+/// most a junit report may be
+const JUNIT_LIMIT: usize = 16 << 20;
+
+enum ReadError {
+    Missing,
+    TooBig,
+}
+
+/// the files at a path in a stopped container, which may be a single file or a
+/// whole directory. stops downloading once the archive gets past `limit` bytes
+async fn read_files(
+    docker: &Docker,
+    id: &str,
+    path: &str,
+    limit: usize,
+) -> Result<Vec<crate::tar::File>, ReadError> {
+    let opts = DownloadFromContainerOptions { path: path.into() };
+    let mut archive = vec![];
+    let mut chunks = docker.download_from_container(id, Some(opts));
+    while let Some(chunk) = chunks.next().await {
+        archive.extend_from_slice(&chunk.map_err(|_| ReadError::Missing)?);
+        if archive.len() > limit {
+            return Err(ReadError::TooBig);
+        }
+    }
+    Ok(crate::tar::files(&archive))
+}
+
+/// one file out of a stopped container
+async fn read_file(
+    docker: &Docker,
+    id: &str,
+    path: &str,
+    limit: usize,
+) -> Result<Vec<u8>, ReadError> {
+    read_files(docker, id, path, limit)
+        .await?
+        .into_iter()
+        .next()
+        .map(|f| f.data)
+        .ok_or(ReadError::Missing)
+}
+
+/// why an artifact of `size` bytes can't be sent when `left` bytes of the
+/// task's budget remain, if it can't
+fn artifact_too_big(size: usize, left: usize) -> Option<String> {
+    if size > ARTIFACT_FILE_LIMIT {
+        Some(format!("bigger than {} MiB", ARTIFACT_FILE_LIMIT >> 20))
+    } else if size > left {
+        Some(format!(
+            "the task's artifacts would go over {} MiB",
+            ARTIFACTS_LIMIT >> 20
+        ))
+    } else {
+        None
+    }
 }
 
 async fn health() -> &'static str {
     "ok\n"
 }
 
-async fn handle_run(docker: Docker, Json(req): Json<RunRequest>) -> EventStream {
-    let (tx, rx) = tokio::sync::mpsc::channel(16);
+async fn info(State(state): State<Arc<WorkerState>>) -> Json<InfoResponse> {
+    Json(InfoResponse {
+        labels: state.labels.clone(),
+        capacity: state.capacity,
+    })
+}
+
+async fn status(State(state): State<Arc<WorkerState>>) -> Json<StatusResponse> {
+    Json(StatusResponse {
+        running: state.capacity.unwrap_or(Semaphore::MAX_PERMITS) - state.slots.available_permits(),
+        queued: state.waiting.len(),
+        capacity: state.capacity,
+    })
+}
+
+/// wait in line for a container slot, telling the client where it stands with
+/// `queued` events. None if the client went away first
+async fn wait_for_slot(
+    state: &WorkerState,
+    tx: &mpsc::Sender<Result<Event, Infallible>>,
+) -> Option<OwnedSemaphorePermit> {
+    let ticket = state.waiting.join();
+    let mut changed = state.waiting.changed.subscribe();
+    let acquire = state.slots.clone().acquire_owned();
+    tokio::pin!(acquire);
+    let mut reported = None;
+    let permit = loop {
+        let position = state.waiting.position(ticket);
+        if position != reported {
+            let event = Event::default()
+                .event("queued")
+                .data(position.unwrap_or_default().to_string());
+            if tx.send(Ok(event)).await.is_err() {
+                break None;
+            }
+            reported = position;
+        }
+        tokio::select! {
+            permit = &mut acquire => break permit.ok(),
+            _ = changed.changed() => {}
+            _ = tx.closed() => break None,
+        }
+    };
+    state.waiting.leave(ticket);
+    permit
+}
+
+/// kill (if running) and remove a container we started. used both by the kill
+/// endpoint and when the client streaming its logs goes away
+async fn kill_container(state: &WorkerState, id: &str) -> Result<(), bollard::errors::Error> {
+    let opts = RemoveContainerOptions {
+        force: true,
+        ..Default::default()
+    };
+    let result = state
+        .docker
+        .remove_container(id, Some(opts))
+        .await;
+    state.containers.lock().unwrap().remove(id);
+    result
+}
+
+async fn handle_run(
+    State(state): State<Arc<WorkerState>>,
+    Json(mut req): Json<RunRequest>,
+) -> EventStream {
+    let (tx, rx) = mpsc::channel(16);
+    if let Some(checkout) = &req.checkout
+        && !crate::git::is_sha(&checkout.sha)
+    {
+        let _ = tx.try_send(Ok(Event::default()
+            .event("error")
+            .data(format!("{} isn't a full commit sha", checkout.sha))));
+        return Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default());
+    }
+    let command = match &req.checkout {
+        Some(checkout) => checkout.wrap(req.command, &mut req.env),
+        None => req.command,
+    };
 
     tokio::spawn(async move {
+        // held until the container is gone
+        let _permit = match state.slots.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => match wait_for_slot(&state, &tx).await {
+                Some(permit) => permit,
+                None => return,
+            },
+        };
+        let docker = &state.docker;
         let id = match docker
             .create_container(
                 None::<CreateContainerOptions>,
                 ContainerCreateBody {
                     image: Some(req.image),
-                    cmd: Some(vec!["sh".into(), "-c".into(), req.command]),
+                    cmd: Some(vec!["sh".into(), "-c".into(), command]),
+                    env: Some(
+                        req.env
+                            .iter()
+                            .map(|(k, v)| format!("{k}={v}"))
+                            .collect(),
+                    ),
+                    host_config: Some(req.limits.host_config()),
                     ..Default::default()
                 },
             )
@@ -50,6 +421,11 @@ async fn handle_run(docker: Docker, Json(req): Json<RunRequest>) -> EventStream
             }
         };
 
+        state
+            .containers
+            .lock()
+            .unwrap()
+            .insert(id.clone());
         docker.start_container(&id, None).await.ok();
         let _ = tx
             .send(Ok(Event::default()
@@ -65,43 +441,178 @@ async fn handle_run(docker: Docker, Json(req): Json<RunRequest>) -> EventStream
             ..Default::default()
         };
         let mut logs = docker.logs(&id, Some(log_opts));
-        while let Some(Ok(log)) = logs.next().await {
-            for line in log.to_string().lines() {
-                let _ = tx
-                    .send(Ok(Event::default().event("log").data(line)))
-                    .await;
+        let timeout = async {
+            match req.timeout_secs {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(timeout);
+        let mut client_gone = false;
+        let mut timed_out = false;
+        while !client_gone && !timed_out {
+            tokio::select! {
+                log = logs.next() => {
+                    let Some(Ok(log)) = log else { break };
+                    for line in log.to_string().lines() {
+                        if tx.send(Ok(Event::default().event("log").data(line))).await.is_err() {
+                            client_gone = true;
+                            break;
+                        }
+                    }
+                }
+                // quiet containers never hit a failed send, so watch the channel too
+                _ = tx.closed() => client_gone = true,
+                _ = &mut timeout => timed_out = true,
             }
         }
+        if client_gone {
+            // nobody is listening for the result — don't leave it running forever
+            println!("client disconnected, removing container {id}");
+            kill_container(&state, &id).await.ok();
+            return;
+        }
+        if timed_out {
+            kill_container(&state, &id).await.ok();
+            let secs = req.timeout_secs.unwrap_or_default();
+            let _ = tx
+                .send(Ok(Event::default()
+                    .event("timeout")
+                    .data(secs.to_string())))
+                .await;
+            return;
+        }
 
         // container is stopped now, inspect to get exit code
-        let exit_code = docker
+        let container_state = docker
             .inspect_container(&id, None)
             .await
             .ok()
-            .and_then(|info| info.state)
-            .and_then(|state| state.exit_code)
-            .unwrap_or(-1);
+            .and_then(|info| info.state);
+        let done = DoneEvent {
+            exit_code: container_state
+                .as_ref()
+                .and_then(|s| s.exit_code)
+                .unwrap_or(-1),
+            oom_killed: container_state
+                .as_ref()
+                .and_then(|s| s.oom_killed)
+                .unwrap_or(false),
+        };
+
+        if let Some(path) = &req.junit {
+            let event = match read_file(docker, &id, path, JUNIT_LIMIT).await {
+                // json, so the whole report fits on one data line
+                Ok(xml) => Event::default()
+                    .event("junit")
+                    .data(serde_json::to_string(&String::from_utf8_lossy(&xml)).unwrap()),
+                Err(ReadError::Missing) => Event::default()
+                    .event("log")
+                    .data(format!("no junit report at {path}")),
+                Err(ReadError::TooBig) => Event::default().event("log").data(format!(
+                    "skipped junit report {path}: bigger than {} MiB",
+                    JUNIT_LIMIT >> 20
+                )),
+            };
+            let _ = tx.send(Ok(event)).await;
+        }
+
+        let mut left = ARTIFACTS_LIMIT;
+        for path in &req.artifacts {
+            let message = match read_files(docker, &id, path, left).await {
+                Ok(files) => {
+                    for file in files {
+                        if let Some(why) = artifact_too_big(file.data.len(), left) {
+                            let _ = tx
+                                .send(Ok(Event::default()
+                                    .event("log")
+                                    .data(format!("skipped artifact {}: {why}", file.path))))
+                                .await;
+                            continue;
+                        }
+                        left -= file.data.len();
+                        let artifact = ArtifactEvent {
+                            path: file.path,
+                            data: BASE64_STANDARD.encode(&file.data),
+                        };
+                        let _ = tx
+                            .send(Ok(Event::default()
+                                .event("artifact")
+                                .data(serde_json::to_string(&artifact).unwrap())))
+                            .await;
+                    }
+                    continue;
+                }
+                Err(ReadError::Missing) => format!("no artifact at {path}"),
+                Err(ReadError::TooBig) => format!(
+                    "skipped artifact {path}: the task's artifacts would go over {} MiB",
+                    ARTIFACTS_LIMIT >> 20
+                ),
+            };
+            let _ = tx
+                .send(Ok(Event::default().event("log").data(message)))
+                .await;
+        }
 
         let _ = tx
             .send(Ok(Event::default()
                 .event("done")
-                .data(exit_code.to_string())))
+                .data(serde_json::to_string(&done).unwrap())))
             .await;
         docker.remove_container(&id, None).await.ok();
+        state.containers.lock().unwrap().remove(&id);
     });
 
-    Sse::new(ReceiverStream::new(rx))
+    // keep-alive comments make a dead client show up as a failed write
+    Sse::new(ReceiverStream::new(rx)).keep_alive(KeepAlive::default())
 }
 
-pub async fn run() {
+async fn handle_kill(State(state): State<Arc<WorkerState>>, Path(id): Path<String>) -> StatusCode {
+    if !state.containers.lock().unwrap().contains(&id) {
+        return StatusCode::NOT_FOUND;
+    }
+    // the log stream in handle_run ends and it reports done as usual
+    match kill_container(&state, &id).await {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub async fn run(opts: WorkerOptions) {
     let docker = Docker::connect_with_local_defaults().expect("failed to connect to docker");
+    let capacity = opts.max_containers.map(|n| n.max(1));
+    let labels: Vec<_> = opts
+        .labels
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    let state = Arc::new(WorkerState {
+        docker,
+        containers: Mutex::new(HashSet::new()),
+        slots: Arc::new(Semaphore::new(capacity.unwrap_or(Semaphore::MAX_PERMITS))),
+        capacity,
+        waiting: SlotQueue::default(),
+        labels: opts.labels,
+    });
 
-    let router = Router::new()
+    let mut router = Router::new()
+        .route("/info", get(info))
+        .route("/status", get(status))
+        .route("/run", post(handle_run))
+        .route("/run/{id}", delete(handle_kill));
+    match opts.token {
+        Some(token) => {
+            let check = middleware::from_fn_with_state(Arc::new(token), require_token);
+            router = router.route_layer(check);
+        }
+        None => println!(
+            "warning: no --worker-token, anyone who can reach port 9009 can run containers here"
+        ),
+    }
+    // health checks stay open, so a boss with the wrong token still sees the worker is up
+    let router = router
         .route("/health", get(health))
-        .route(
-            "/run",
-            post(move |req: Json<RunRequest>| handle_run(docker, req)),
-        );
+        .with_state(state);
 
     let socket = tokio::net::TcpSocket::new_v4().unwrap();
     socket.set_reuseaddr(true).unwrap();
@@ -109,6 +620,51 @@ pub async fn run() {
         .bind("0.0.0.0:9009".parse().unwrap())
         .unwrap();
     let listener = socket.listen(1024).unwrap();
-    println!("bettertest worker running on http://0.0.0.0:9009");
+    match capacity {
+        Some(n) => {
+            println!("bettertest worker running on http://0.0.0.0:9009, {n} containers at a time")
+        }
+        None => println!("bettertest worker running on http://0.0.0.0:9009"),
+    }
+    if !labels.is_empty() {
+        println!("labels: {}", labels.join(" "));
+    }
     axum::serve(listener, router).await.unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_positions_move_up() {
+        let queue = SlotQueue::default();
+        let a = queue.join();
+        let b = queue.join();
+        let c = queue.join();
+        assert_eq!(queue.position(c), Some(3));
+        queue.leave(a);
+        assert_eq!(queue.position(b), Some(1));
+        assert_eq!(queue.position(c), Some(2));
+        assert_eq!(queue.position(a), None);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn token_has_to_match_exactly() {
+        assert!(authorized("s3cret", Some("Bearer s3cret")));
+        assert!(!authorized("s3cret", Some("Bearer s3cre")));
+        assert!(!authorized("s3cret", Some("Bearer s3cret2")));
+        assert!(!authorized("s3cret", Some("s3cret")));
+        assert!(!authorized("s3cret", Some("Basic s3cret")));
+        assert!(!authorized("s3cret", None));
+    }
+
+    #[test]
+    fn artifacts_stay_within_limits() {
+        assert_eq!(artifact_too_big(10, ARTIFACTS_LIMIT), None);
+        assert_eq!(artifact_too_big(10, 10), None);
+        assert!(artifact_too_big(ARTIFACT_FILE_LIMIT + 1, ARTIFACTS_LIMIT).is_some());
+        assert!(artifact_too_big(11, 10).is_some());
+    }
+}